@@ -0,0 +1,206 @@
+use bevy::{
+    input::InputSystem,
+    prelude::*,
+    utils::{HashMap, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{osc::OscType, pot::PotType, settings::Settings};
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct ActionSet;
+
+pub(super) struct ActionPlugin;
+
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (clear_actions, keyboard_actions)
+                .chain()
+                .in_set(ActionSet)
+                .after(InputSystem),
+        );
+        app.init_resource::<ButtonInput<Action>>();
+        app.init_resource::<ActionTimes>();
+    }
+}
+
+/// Something the player can do, independent of the key or button that does it.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum Action {
+    Osc(OscType),
+    Pot(PotType),
+    Pause,
+    Menu,
+    /// Sweep the free mode low-pass cutoff up while held.
+    CutoffUp,
+    CutoffDown,
+    /// Raise the free mode filter resonance while held.
+    ResonanceUp,
+    ResonanceDown,
+    /// Step the free mode delay through its mix levels.
+    DelayMix,
+    /// Step the free mode bitcrush through its bit depths.
+    BitDepth,
+    /// Tap along with the calibration click.
+    Tap,
+}
+
+impl Action {
+    /// Actions the controls screen lets the player rebind.
+    pub(crate) const REBINDABLE: [Action; 17] = [
+        Action::Osc(OscType::Sine),
+        Action::Osc(OscType::Triangle),
+        Action::Osc(OscType::Square),
+        Action::Osc(OscType::Sawtooth),
+        Action::Pot(PotType::PotJ),
+        Action::Pot(PotType::PotI),
+        Action::Pot(PotType::PotK),
+        Action::Pot(PotType::PotO),
+        Action::Pot(PotType::PotL),
+        Action::Pause,
+        Action::CutoffUp,
+        Action::CutoffDown,
+        Action::ResonanceUp,
+        Action::ResonanceDown,
+        Action::DelayMix,
+        Action::BitDepth,
+        Action::Tap,
+    ];
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Action::Osc(OscType::Sine) => "SINE",
+            Action::Osc(OscType::Triangle) => "TRIANGLE",
+            Action::Osc(OscType::Square) => "SQUARE",
+            Action::Osc(OscType::Sawtooth) => "SAW",
+            Action::Pot(PotType::PotJ) => "POT 1",
+            Action::Pot(PotType::PotI) => "POT 2",
+            Action::Pot(PotType::PotK) => "POT 3",
+            Action::Pot(PotType::PotO) => "POT 4",
+            Action::Pot(PotType::PotL) => "POT 5",
+            Action::Pause => "PAUSE",
+            Action::Menu => "MENU",
+            Action::CutoffUp => "CUTOFF UP",
+            Action::CutoffDown => "CUTOFF DOWN",
+            Action::ResonanceUp => "RES UP",
+            Action::ResonanceDown => "RES DOWN",
+            Action::DelayMix => "DELAY",
+            Action::BitDepth => "BITCRUSH",
+            Action::Tap => "CALIBRATION TAP",
+        }
+    }
+}
+
+/// Key each action is bound to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct KeyBindings(Vec<(Action, KeyCode)>);
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings(vec![
+            (Action::Osc(OscType::Sine), KeyCode::KeyA),
+            (Action::Osc(OscType::Triangle), KeyCode::KeyW),
+            (Action::Osc(OscType::Square), KeyCode::KeyS),
+            (Action::Osc(OscType::Sawtooth), KeyCode::KeyD),
+            (Action::Pot(PotType::PotJ), KeyCode::KeyJ),
+            (Action::Pot(PotType::PotI), KeyCode::KeyI),
+            (Action::Pot(PotType::PotK), KeyCode::KeyK),
+            (Action::Pot(PotType::PotO), KeyCode::KeyO),
+            (Action::Pot(PotType::PotL), KeyCode::KeyL),
+            (Action::Pause, KeyCode::Backquote),
+            (Action::Menu, KeyCode::Escape),
+            (Action::CutoffUp, KeyCode::ArrowUp),
+            (Action::CutoffDown, KeyCode::ArrowDown),
+            (Action::ResonanceUp, KeyCode::ArrowRight),
+            (Action::ResonanceDown, KeyCode::ArrowLeft),
+            (Action::DelayMix, KeyCode::Digit1),
+            (Action::BitDepth, KeyCode::Digit2),
+            (Action::Tap, KeyCode::Space),
+        ])
+    }
+}
+
+impl KeyBindings {
+    pub(crate) fn key(&self, action: Action) -> Option<KeyCode> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, key)| *key)
+    }
+
+    /// Gives actions that saved bindings predate their default key, if it is still free.
+    pub(crate) fn add_missing(&mut self) {
+        for (action, key) in KeyBindings::default().0 {
+            if self.key(action).is_none() && !self.0.iter().any(|(_, bound)| *bound == key) {
+                self.0.push((action, key));
+            }
+        }
+    }
+
+    /// Bind `key` to `action`; whatever held the key before takes the action's old key.
+    pub(crate) fn bind(&mut self, action: Action, key: KeyCode) {
+        match self.key(action) {
+            Some(previous) => {
+                for (bound, bound_key) in self.0.iter_mut() {
+                    if *bound_key == key && *bound != action {
+                        *bound_key = previous;
+                    }
+                }
+            }
+            None => self.0.retain(|(_, bound_key)| *bound_key != key),
+        }
+        match self.0.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, bound_key)) => *bound_key = key,
+            None => self.0.push((action, key)),
+        }
+    }
+}
+
+/// When each action was last pressed, as close to the physical press as its source knows.
+///
+/// How close that is depends on the source:
+/// - MIDI ports stamp each message as it arrives, and scripted MIDI is stamped at the
+///   time the script gives it.
+/// - Keyboard events carry no timestamp, so key presses count as landing when the frame
+///   started.
+/// - Gamepad and touch events carry no timestamp either, and gamepad state is only
+///   polled once a frame, so those presses are stamped as they are read.
+#[derive(Resource, Default)]
+pub(crate) struct ActionTimes(HashMap<Action, Instant>);
+
+impl ActionTimes {
+    /// Press `action` on `actions`, remembering that it happened at `at`.
+    pub(crate) fn press(&mut self, actions: &mut ButtonInput<Action>, action: Action, at: Instant) {
+        actions.press(action);
+        self.0.insert(action, at);
+    }
+
+    pub(crate) fn pressed_at(&self, action: Action) -> Option<Instant> {
+        self.0.get(&action).copied()
+    }
+}
+
+pub(crate) fn clear_actions(mut actions: ResMut<ButtonInput<Action>>) {
+    actions.bypass_change_detection().clear();
+}
+
+fn keyboard_actions(
+    keys: Res<ButtonInput<KeyCode>>,
+    settings: Res<Settings>,
+    real: Res<Time<Real>>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut times: ResMut<ActionTimes>,
+) {
+    // key presses count as landing when the frame started, see `ActionTimes`
+    let now = real.last_update().unwrap_or_else(Instant::now);
+    for (action, key) in settings.bindings.0.iter() {
+        if keys.just_pressed(*key) {
+            times.press(&mut actions, *action, now);
+        }
+        if keys.just_released(*key) {
+            actions.release(*action);
+        }
+    }
+}
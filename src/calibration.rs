@@ -0,0 +1,238 @@
+use bevy::{audio::Volume, prelude::*, time::Stopwatch, utils::Instant};
+
+use crate::{
+    action::{Action, ActionTimes},
+    effects::SharedEffects,
+    osc::OscType,
+    pointer::Touchable,
+    settings::Settings,
+    synth::Voice,
+    ApplicationState,
+};
+
+/// Seconds between clicks, 120 bpm.
+const CLICK_INTERVAL: f64 = 0.5;
+/// Silence before the first click so the player can get ready.
+const FIRST_CLICK: f64 = 1.5;
+const CLICK_SECS: f32 = 0.05;
+/// Taps collected before the offset is worked out.
+const TAPS_NEEDED: usize = 12;
+/// Half the size of the screen area that takes taps from a mouse or finger.
+const TAP_AREA: Vec2 = Vec2::new(640., 360.);
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct CalibrationSet;
+
+pub(super) struct CalibrationPlugin;
+
+impl Plugin for CalibrationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnEnter(ApplicationState::Calibration),
+            start_calibration.in_set(CalibrationSet),
+        );
+        app.add_systems(
+            Update,
+            (play_clicks, record_taps, calibration_display)
+                .chain()
+                .in_set(CalibrationSet)
+                .run_if(in_state(ApplicationState::Calibration)),
+        );
+        app.add_systems(
+            OnExit(ApplicationState::Calibration),
+            clear_calibration.in_set(CalibrationSet),
+        );
+        app.init_resource::<Calibration>();
+    }
+}
+
+#[derive(Resource, Default)]
+struct Calibration {
+    clock: Stopwatch,
+    /// When the clock read zero, so taps are timed from their press rather than the frame.
+    started: Option<Instant>,
+    clicks: u32,
+    /// Seconds between each tap and its nearest click; negative when early.
+    taps: Vec<f64>,
+}
+
+impl Calibration {
+    fn click_secs(index: u32) -> f64 {
+        FIRST_CLICK + index as f64 * CLICK_INTERVAL
+    }
+
+    /// Click a tap at `secs` was aiming for: one that has played or the one about to. Taps
+    /// more than half an interval before the first click or after that are stray.
+    fn nearest_click(&self, secs: f64) -> Option<u32> {
+        let nearest = ((secs - FIRST_CLICK) / CLICK_INTERVAL).round();
+        (nearest >= 0. && nearest <= self.clicks as f64).then_some(nearest as u32)
+    }
+
+    /// Counts a tap pressed at `at` against the click it was aiming for.
+    fn record_tap(&mut self, at: Instant) {
+        let Some(started) = self.started else {
+            return;
+        };
+        let secs = at.saturating_duration_since(started).as_secs_f64();
+        if let Some(nearest) = self.nearest_click(secs) {
+            self.taps.push(secs - Calibration::click_secs(nearest));
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.taps.len() >= TAPS_NEEDED
+    }
+
+    /// Median tap offset, which shrugs off the odd fumbled tap.
+    fn offset_secs(&self) -> f64 {
+        let mut taps = self.taps.clone();
+        taps.sort_by(f64::total_cmp);
+        let len = taps.len();
+        (taps[(len - 1) / 2] + taps[len / 2]) / 2.
+    }
+}
+
+#[derive(Component)]
+struct CalibrationDispTag;
+
+fn start_calibration(mut commands: Commands, mut calibration: ResMut<Calibration>) {
+    *calibration = Calibration::default();
+    let home = Vec3::new(0., 0., 104.);
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 28.,
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(home),
+            ..default()
+        },
+        // anywhere on screen will do for a click or tap
+        Touchable::area(Action::Tap, home, TAP_AREA),
+        CalibrationDispTag,
+    ));
+}
+
+fn play_clicks(
+    time: Res<Time>,
+    real: Res<Time<Real>>,
+    mut calibration: ResMut<Calibration>,
+    mut commands: Commands,
+    mut voices: ResMut<Assets<Voice>>,
+) {
+    calibration.clock.tick(time.delta());
+    if calibration.started.is_none() {
+        let frame_start = real.last_update().unwrap_or_else(Instant::now);
+        let elapsed = calibration.clock.elapsed();
+        calibration.started = Some(frame_start.checked_sub(elapsed).unwrap_or(frame_start));
+    }
+    if calibration.done() {
+        return;
+    }
+    let now = calibration.clock.elapsed_secs_f64();
+    while Calibration::click_secs(calibration.clicks) <= now {
+        calibration.clicks += 1;
+        // clicks skip the effects chain so nothing smears their timing
+        let voice = Voice::new(OscType::Sine, 880., &SharedEffects::default()).once(CLICK_SECS);
+        commands.spawn(AudioSourceBundle {
+            source: voices.add(voice),
+            settings: PlaybackSettings {
+                volume: Volume::new(0.5),
+                ..PlaybackSettings::DESPAWN
+            },
+        });
+    }
+}
+
+fn record_taps(
+    actions: Res<ButtonInput<Action>>,
+    times: Res<ActionTimes>,
+    mut calibration: ResMut<Calibration>,
+    mut settings: ResMut<Settings>,
+) {
+    if calibration.done() || !actions.just_pressed(Action::Tap) {
+        return;
+    }
+    let Some(at) = times.pressed_at(Action::Tap) else {
+        return;
+    };
+    calibration.record_tap(at);
+
+    if calibration.done() {
+        settings.audio_offset_ms = (calibration.offset_secs() * 1000.).round();
+        settings.save();
+    }
+}
+
+fn calibration_display(
+    calibration: Res<Calibration>,
+    settings: Res<Settings>,
+    mut query: Query<&mut Text, With<CalibrationDispTag>>,
+) {
+    let message = if calibration.done() {
+        format!(
+            "AUDIO OFFSET: {:+.0} ms\n\nPRESS ESC TO RETURN",
+            settings.audio_offset_ms
+        )
+    } else {
+        let key = settings
+            .bindings
+            .key(Action::Tap)
+            .map_or_else(String::new, |key| format!(" {:?}", key).to_uppercase());
+        format!(
+            "TAP{} ALONG WITH THE CLICK\n\n{} / {}",
+            key,
+            calibration.taps.len(),
+            TAPS_NEEDED
+        )
+    };
+    for mut text in query.iter_mut() {
+        text.sections[0].value.clone_from(&message);
+    }
+}
+
+fn clear_calibration(mut commands: Commands, query: Query<Entity, With<CalibrationDispTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taps_outside_the_clicks_are_ignored() {
+        let calibration = Calibration {
+            clicks: 2,
+            ..default()
+        };
+        assert_eq!(calibration.nearest_click(FIRST_CLICK - 0.3), None);
+        assert_eq!(calibration.nearest_click(FIRST_CLICK - 0.2), Some(0));
+        assert_eq!(calibration.nearest_click(FIRST_CLICK + 0.6), Some(1));
+        // early for the click about to play
+        assert_eq!(calibration.nearest_click(FIRST_CLICK + 0.9), Some(2));
+        assert_eq!(calibration.nearest_click(FIRST_CLICK + 1.3), None);
+    }
+
+    #[test]
+    fn taps_are_timed_from_their_press() {
+        let started = Instant::now();
+        let mut calibration = Calibration {
+            started: Some(started),
+            clicks: 2,
+            ..default()
+        };
+        let at = |secs: f64| started + std::time::Duration::from_secs_f64(secs);
+
+        calibration.record_tap(at(FIRST_CLICK + 0.03));
+        calibration.record_tap(at(FIRST_CLICK + CLICK_INTERVAL - 0.02));
+        calibration.record_tap(at(FIRST_CLICK - 0.3));
+        assert_eq!(calibration.taps.len(), 2);
+        assert!((calibration.taps[0] - 0.03).abs() < 1e-6);
+        assert!((calibration.taps[1] + 0.02).abs() < 1e-6);
+    }
+}
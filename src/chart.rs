@@ -0,0 +1,247 @@
+use std::fmt;
+
+use bevy::{
+    asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext, LoadState},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    effects::EffectEvent,
+    scale::Key,
+    tempo::{Tempo, TempoEvent, TempoMap},
+    track::{Seq, Track, TrackClock},
+    ApplicationState,
+};
+
+const DEFAULT_CHART: &str = "charts/tj_01.chart.json";
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct ChartSet;
+
+pub(super) struct ChartPlugin;
+
+impl Plugin for ChartPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<Chart>().init_asset_loader::<ChartLoader>();
+        app.add_systems(Startup, load_chart);
+        app.add_systems(
+            Update,
+            wait_for_chart
+                .in_set(ChartSet)
+                .run_if(in_state(ApplicationState::Loading)),
+        );
+        app.add_systems(OnExit(ApplicationState::Loading), clear_chart_error);
+        app.add_systems(
+            Update,
+            reload_chart
+                .in_set(ChartSet)
+                .run_if(in_state(ApplicationState::InGame)),
+        );
+        app.add_event::<ChartReloadedEvent>();
+    }
+}
+
+/// On-disk description of a playable track.
+#[derive(Asset, TypePath, Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Chart {
+    pub(crate) tempo: Tempo,
+    /// Tempo changes at absolute ticks, in order.
+    #[serde(default)]
+    pub(crate) tempo_changes: Vec<TempoEvent>,
+    #[serde(skip)]
+    pub(crate) tempo_map: TempoMap,
+    /// Key the pots play in; A3 dorian when left out.
+    #[serde(default)]
+    pub(crate) key: Key,
+    /// Filter, delay and bitcrush changes at absolute ticks, in order.
+    #[serde(default)]
+    pub(crate) effects: Vec<EffectEvent>,
+    /// Bars to play before the backing audio starts.
+    pub(crate) lead_in: u64,
+    /// Backing song; without one the track plays to the clock alone.
+    #[serde(default)]
+    pub(crate) audio: Option<String>,
+    pub(crate) steps: u64,
+    /// Number of times the sequence plays before the song ends.
+    pub(crate) loops: u64,
+    pub(crate) seq: Vec<Seq>,
+}
+
+impl Chart {
+    fn validate(&self) -> Result<(), ChartLoaderError> {
+        self.tempo.validate().map_err(ChartLoaderError::Invalid)?;
+        self.key.validate().map_err(ChartLoaderError::Invalid)?;
+        EffectEvent::validate(&self.effects).map_err(ChartLoaderError::Invalid)?;
+        if self.loops == 0 {
+            return Err(ChartLoaderError::Invalid(
+                "chart must play at least once".into(),
+            ));
+        }
+        if self.seq.is_empty() {
+            return Err(ChartLoaderError::Invalid("chart has no notes".into()));
+        }
+        let mut last: Option<u64> = None;
+        for seq in self.seq.iter() {
+            if last.is_some_and(|last| seq.time <= last) || seq.time >= self.steps {
+                return Err(ChartLoaderError::Invalid(format!(
+                    "note time {} must be increasing and within 0..{}",
+                    seq.time, self.steps
+                )));
+            }
+            if seq.note.s2 == Some(seq.note.s1) {
+                return Err(ChartLoaderError::Invalid(format!(
+                    "chord at time {} repeats {:?}",
+                    seq.time, seq.note.s1
+                )));
+            }
+            last = Some(seq.time);
+        }
+        for (index, seq) in self.seq.iter().enumerate() {
+            let next = match self.seq.get(index + 1) {
+                Some(next) => next.time,
+                None => self.steps + self.seq[0].time,
+            };
+            if seq.hold > 0 && seq.time + seq.hold >= next {
+                return Err(ChartLoaderError::Invalid(format!(
+                    "hold at time {} runs into the next note",
+                    seq.time
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum ChartLoaderError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ChartLoaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChartLoaderError::Io(err) => write!(f, "could not read chart: {}", err),
+            ChartLoaderError::Json(err) => write!(f, "could not parse chart: {}", err),
+            ChartLoaderError::Invalid(msg) => write!(f, "invalid chart: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ChartLoaderError {}
+
+impl From<std::io::Error> for ChartLoaderError {
+    fn from(err: std::io::Error) -> Self {
+        ChartLoaderError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ChartLoaderError {
+    fn from(err: serde_json::Error) -> Self {
+        ChartLoaderError::Json(err)
+    }
+}
+
+#[derive(Default)]
+struct ChartLoader;
+
+impl AssetLoader for ChartLoader {
+    type Asset = Chart;
+    type Settings = ();
+    type Error = ChartLoaderError;
+
+    async fn load<'a>(
+        &'a self,
+        reader: &'a mut Reader<'_>,
+        _settings: &'a (),
+        _load_context: &'a mut LoadContext<'_>,
+    ) -> Result<Self::Asset, Self::Error> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).await?;
+        let mut chart: Chart = serde_json::from_slice(&bytes)?;
+        chart.validate()?;
+        chart.tempo_map =
+            TempoMap::new(chart.tempo, &chart.tempo_changes).map_err(ChartLoaderError::Invalid)?;
+        Ok(chart)
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["chart.json"]
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct ChartHandle(pub(crate) Handle<Chart>);
+
+fn load_chart(mut commands: Commands, server: Res<AssetServer>) {
+    commands.insert_resource(ChartHandle(server.load(DEFAULT_CHART)));
+}
+
+#[derive(Component)]
+struct ChartErrorTag;
+
+fn wait_for_chart(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    charts: Res<Assets<Chart>>,
+    handle: Res<ChartHandle>,
+    mut track: ResMut<Track>,
+    error_query: Query<Entity, With<ChartErrorTag>>,
+    mut next_app_state: ResMut<NextState<ApplicationState>>,
+) {
+    match server.get_load_state(&handle.0) {
+        Some(LoadState::Loaded) => {
+            if let Some(chart) = charts.get(&handle.0) {
+                track.apply_chart(chart);
+                next_app_state.set(ApplicationState::InGame);
+            }
+        }
+        Some(LoadState::Failed(err)) if error_query.is_empty() => {
+            error!("{}", err);
+            commands.spawn((
+                Text2dBundle {
+                    text: Text::from_section(
+                        format!("CHART ERROR: {}", err),
+                        TextStyle {
+                            font_size: 16.,
+                            ..default()
+                        },
+                    ),
+                    transform: Transform::from_translation(Vec3::new(4., -48., 104.)),
+                    ..default()
+                },
+                ChartErrorTag,
+            ));
+        }
+        _ => {}
+    }
+}
+
+fn clear_chart_error(mut commands: Commands, query: Query<Entity, With<ChartErrorTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[derive(Event)]
+pub(crate) struct ChartReloadedEvent;
+
+fn reload_chart(
+    mut ev_asset: EventReader<AssetEvent<Chart>>,
+    charts: Res<Assets<Chart>>,
+    handle: Res<ChartHandle>,
+    clock: Res<TrackClock>,
+    mut track: ResMut<Track>,
+    mut ev_reloaded: EventWriter<ChartReloadedEvent>,
+) {
+    for ev in ev_asset.read() {
+        if ev.is_modified(&handle.0) {
+            if let Some(chart) = charts.get(&handle.0) {
+                track.reload_chart(chart, clock.elapsed_secs());
+                ev_reloaded.send(ChartReloadedEvent);
+            }
+        }
+    }
+}
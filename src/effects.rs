@@ -0,0 +1,495 @@
+use std::{
+    f32::consts::PI,
+    sync::{Arc, RwLock},
+};
+
+use bevy::{prelude::*, sprite::Anchor};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::Action,
+    track::{Track, TrackClock, TrackSet},
+    ApplicationState, ModeState, PauseState,
+};
+
+const SAMPLE_RATE: f32 = 44_100.;
+/// Longest delay time a voice keeps a buffer for, in seconds.
+const MAX_DELAY_SECS: f32 = 1.;
+/// Samples between checks for new effect settings from the game thread.
+const REFRESH_SAMPLES: u32 = 256;
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct EffectsSet;
+
+pub(super) struct EffectsPlugin;
+
+impl Plugin for EffectsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnExit(ApplicationState::Loading),
+            (reset_effects, load_effects_display).in_set(EffectsSet),
+        );
+        app.add_systems(
+            FixedUpdate,
+            automate_effects
+                .in_set(EffectsSet)
+                .after(TrackSet)
+                .run_if(in_state(ApplicationState::InGame))
+                .run_if(in_state(ModeState::Singleplayer))
+                .run_if(in_state(PauseState::Unpaused)),
+        );
+        app.add_systems(
+            Update,
+            (freeform_effects, effects_display)
+                .chain()
+                .in_set(EffectsSet)
+                .run_if(in_state(ApplicationState::InGame))
+                .run_if(in_state(ModeState::Freeform)),
+        );
+        app.add_systems(Update, publish_effects.in_set(EffectsSet));
+        app.add_systems(OnEnter(ModeState::NotInGame), unload_effects_display);
+        app.init_resource::<Effects>();
+        app.init_resource::<SharedEffects>();
+        app.init_resource::<EffectAutomation>();
+    }
+}
+
+/// Settings for the filter, delay and bitcrush every voice runs through.
+#[derive(Resource, PartialEq, Clone, Copy, Debug)]
+pub(crate) struct Effects {
+    /// Low-pass cutoff in Hz.
+    pub(crate) cutoff: f32,
+    /// Filter resonance, between 0 and 1.
+    pub(crate) resonance: f32,
+    /// Delay time in seconds.
+    pub(crate) delay_secs: f32,
+    /// Share of each echo fed back into the delay, between 0 and 1.
+    pub(crate) delay_feedback: f32,
+    /// Level of the echoes against the dry signal, between 0 and 1.
+    pub(crate) delay_mix: f32,
+    /// Bit depth the signal is crushed to; 16 leaves it untouched.
+    pub(crate) bits: u8,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Effects {
+            cutoff: 20_000.,
+            resonance: 0.,
+            delay_secs: 0.25,
+            delay_feedback: 0.35,
+            delay_mix: 0.,
+            bits: 16,
+        }
+    }
+}
+
+impl Effects {
+    fn apply(&mut self, change: &EffectChange) {
+        match *change {
+            EffectChange::Cutoff { hz } => self.cutoff = hz,
+            EffectChange::Resonance { amount } => self.resonance = amount,
+            EffectChange::Delay {
+                secs,
+                feedback,
+                mix,
+            } => {
+                self.delay_secs = secs;
+                self.delay_feedback = feedback;
+                self.delay_mix = mix;
+            }
+            EffectChange::Bitcrush { bits } => self.bits = bits,
+        }
+    }
+}
+
+/// Copy of [`Effects`] that the audio thread reads from.
+#[derive(Resource, Clone, Default, Debug)]
+pub(crate) struct SharedEffects(Arc<RwLock<Effects>>);
+
+fn publish_effects(effects: Res<Effects>, shared: Res<SharedEffects>) {
+    if effects.is_changed() {
+        if let Ok(mut params) = shared.0.write() {
+            *params = *effects;
+        }
+    }
+}
+
+/// Effect change at an absolute tick of the chart.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct EffectEvent {
+    pub(crate) tick: f64,
+    pub(crate) change: EffectChange,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum EffectChange {
+    Cutoff { hz: f32 },
+    Resonance { amount: f32 },
+    Delay { secs: f32, feedback: f32, mix: f32 },
+    Bitcrush { bits: u8 },
+}
+
+impl EffectEvent {
+    pub(crate) fn validate(events: &[EffectEvent]) -> Result<(), String> {
+        if events.windows(2).any(|pair| pair[1].tick < pair[0].tick) {
+            return Err("effect changes must be in order".into());
+        }
+        for event in events {
+            let valid = match event.change {
+                EffectChange::Cutoff { hz } => (20. ..=20_000.).contains(&hz),
+                EffectChange::Resonance { amount } => (0. ..=1.).contains(&amount),
+                EffectChange::Delay {
+                    secs,
+                    feedback,
+                    mix,
+                } => {
+                    (0. ..=MAX_DELAY_SECS).contains(&secs)
+                        && (0. ..1.).contains(&feedback)
+                        && (0. ..=1.).contains(&mix)
+                }
+                EffectChange::Bitcrush { bits } => (1..=16).contains(&bits),
+            };
+            if !valid {
+                return Err(format!(
+                    "effect change at tick {} is out of range: {:?}",
+                    event.tick, event.change
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Index of the next chart effect change to apply.
+#[derive(Resource, Default)]
+struct EffectAutomation {
+    next: usize,
+}
+
+fn reset_effects(mut effects: ResMut<Effects>, mut automation: ResMut<EffectAutomation>) {
+    *effects = Effects::default();
+    automation.next = 0;
+}
+
+fn automate_effects(
+    clock: Res<TrackClock>,
+    track: Res<Track>,
+    mut automation: ResMut<EffectAutomation>,
+    mut effects: ResMut<Effects>,
+) {
+    let ticks = track
+        .sequencer
+        .tempo_map()
+        .secs_to_ticks(clock.elapsed_secs());
+    while let Some(event) = track.effects.get(automation.next) {
+        if event.tick > ticks {
+            break;
+        }
+        effects.apply(&event.change);
+        automation.next += 1;
+    }
+}
+
+/// Filter and delay state for one voice.
+pub(crate) struct EffectChain {
+    shared: Arc<RwLock<Effects>>,
+    params: Effects,
+    until_refresh: u32,
+    // state-variable filter integrators
+    ic1: f32,
+    ic2: f32,
+    delay: Vec<f32>,
+    delay_pos: usize,
+    /// Consecutive quiet samples written to the delay line.
+    quiet: usize,
+}
+
+impl EffectChain {
+    pub(crate) fn new(shared: &SharedEffects) -> Self {
+        let params = shared.0.read().map(|params| *params).unwrap_or_default();
+        EffectChain {
+            shared: shared.0.clone(),
+            params,
+            until_refresh: REFRESH_SAMPLES,
+            ic1: 0.,
+            ic2: 0.,
+            delay: vec![0.; (MAX_DELAY_SECS * SAMPLE_RATE) as usize],
+            delay_pos: 0,
+            quiet: 0,
+        }
+    }
+
+    fn delay_samples(&self) -> usize {
+        ((self.params.delay_secs * SAMPLE_RATE) as usize).clamp(1, self.delay.len())
+    }
+
+    /// Whether the delay line has gone silent, so the voice can stop once its note has.
+    pub(crate) fn finished(&self) -> bool {
+        self.params.delay_mix == 0. || self.quiet >= self.delay_samples()
+    }
+
+    pub(crate) fn process(&mut self, input: f32) -> f32 {
+        self.until_refresh -= 1;
+        if self.until_refresh == 0 {
+            self.until_refresh = REFRESH_SAMPLES;
+            if let Ok(params) = self.shared.try_read() {
+                self.params = *params;
+            }
+        }
+
+        let crushed = crush(input, self.params.bits);
+
+        // trapezoidal state-variable low-pass, stable at any cutoff below nyquist
+        let cutoff = self.params.cutoff.min(SAMPLE_RATE * 0.45);
+        let g = (PI * cutoff / SAMPLE_RATE).tan();
+        let k = 2. - 1.9 * self.params.resonance.clamp(0., 1.);
+        let a1 = 1. / (1. + g * (g + k));
+        let a2 = g * a1;
+        let a3 = g * a2;
+        let v3 = crushed - self.ic2;
+        let v1 = a1 * self.ic1 + a2 * v3;
+        let v2 = self.ic2 + a2 * self.ic1 + a3 * v3;
+        self.ic1 = 2. * v1 - self.ic1;
+        self.ic2 = 2. * v2 - self.ic2;
+        let filtered = v2;
+
+        let len = self.delay_samples();
+        let read = (self.delay_pos + self.delay.len() - len) % self.delay.len();
+        let echo = self.delay[read];
+        let written = filtered + echo * self.params.delay_feedback;
+        self.delay[self.delay_pos] = written;
+        self.delay_pos = (self.delay_pos + 1) % self.delay.len();
+        if written.abs() < 1e-4 {
+            self.quiet += 1;
+        } else {
+            self.quiet = 0;
+        }
+
+        filtered + echo * self.params.delay_mix
+    }
+}
+
+/// Rounds `input` to `bits` of depth; at 16 bits it passes through untouched.
+fn crush(input: f32, bits: u8) -> f32 {
+    if bits < 16 {
+        let levels = (1u32 << (bits - 1)) as f32;
+        (input * levels).round() / levels
+    } else {
+        input
+    }
+}
+
+/// Cutoff multiplier per second while the cutoff is swept.
+const CUTOFF_SWEEP: f32 = 4.;
+const BIT_DEPTHS: [u8; 4] = [16, 8, 6, 4];
+const DELAY_MIXES: [f32; 3] = [0., 0.3, 0.6];
+
+fn freeform_effects(
+    actions: Res<ButtonInput<Action>>,
+    time: Res<Time>,
+    mut effects: ResMut<Effects>,
+) {
+    let dt = time.delta_seconds();
+    if actions.pressed(Action::CutoffUp) {
+        effects.cutoff = (effects.cutoff * CUTOFF_SWEEP.powf(dt)).min(20_000.);
+    }
+    if actions.pressed(Action::CutoffDown) {
+        effects.cutoff = (effects.cutoff / CUTOFF_SWEEP.powf(dt)).max(20.);
+    }
+    if actions.pressed(Action::ResonanceUp) {
+        effects.resonance = (effects.resonance + dt).min(1.);
+    }
+    if actions.pressed(Action::ResonanceDown) {
+        effects.resonance = (effects.resonance - dt).max(0.);
+    }
+    if actions.just_pressed(Action::DelayMix) {
+        let index = DELAY_MIXES
+            .iter()
+            .position(|mix| *mix == effects.delay_mix)
+            .map_or(0, |index| (index + 1) % DELAY_MIXES.len());
+        effects.delay_mix = DELAY_MIXES[index];
+    }
+    if actions.just_pressed(Action::BitDepth) {
+        let index = BIT_DEPTHS
+            .iter()
+            .position(|bits| *bits == effects.bits)
+            .map_or(0, |index| (index + 1) % BIT_DEPTHS.len());
+        effects.bits = BIT_DEPTHS[index];
+    }
+}
+
+#[derive(Component)]
+struct EffectsDispTag;
+
+fn load_effects_display(
+    mut commands: Commands,
+    query: Query<Entity, With<EffectsDispTag>>,
+    mode: Res<State<ModeState>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if mode.get() != &ModeState::Freeform {
+        return;
+    }
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 20.,
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(-300., 228., 104.)),
+            text_anchor: Anchor::CenterLeft,
+            ..default()
+        },
+        EffectsDispTag,
+    ));
+}
+
+fn effects_display(effects: Res<Effects>, mut query: Query<&mut Text, With<EffectsDispTag>>) {
+    for mut text in query.iter_mut() {
+        text.sections[0].value = format!(
+            "CUTOFF: {:.0} Hz  RES: {:.2}\nDELAY: {:.0}%  BITS: {}",
+            effects.cutoff,
+            effects.resonance,
+            effects.delay_mix * 100.,
+            effects.bits
+        );
+    }
+}
+
+fn unload_effects_display(mut commands: Commands, query: Query<Entity, With<EffectsDispTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(effects: Effects) -> EffectChain {
+        EffectChain::new(&SharedEffects(Arc::new(RwLock::new(effects))))
+    }
+
+    /// A second of a 440 Hz sine through `chain`.
+    fn render(chain: &mut EffectChain) -> Vec<f32> {
+        (0..SAMPLE_RATE as usize)
+            .map(|n| chain.process((2. * PI * 440. * n as f32 / SAMPLE_RATE).sin()))
+            .collect()
+    }
+
+    #[test]
+    fn cutoff_is_clamped_below_nyquist() {
+        let top = SAMPLE_RATE * 0.45;
+        let clamped = render(&mut chain(Effects {
+            cutoff: 100_000.,
+            resonance: 1.,
+            ..Effects::default()
+        }));
+        let at_top = render(&mut chain(Effects {
+            cutoff: top,
+            resonance: 1.,
+            ..Effects::default()
+        }));
+        assert_eq!(clamped, at_top);
+        assert!(clamped
+            .iter()
+            .all(|sample| sample.is_finite() && sample.abs() < 4.));
+    }
+
+    #[test]
+    fn delay_feedback_dies_away() {
+        let mut chain = chain(Effects {
+            delay_feedback: 0.9,
+            delay_mix: 1.,
+            ..Effects::default()
+        });
+        let period = chain.delay_samples();
+        let mut peaks = Vec::new();
+        chain.process(1.);
+        for echo in 0..200 {
+            let peak = (0..period)
+                .map(|_| chain.process(0.).abs())
+                .fold(0., f32::max);
+            if echo < 3 {
+                peaks.push(peak);
+            }
+        }
+        assert!(peaks[0] > 0.);
+        assert!(peaks.windows(2).all(|pair| pair[1] < pair[0]));
+        assert!(chain.finished());
+
+        let looping = EffectEvent {
+            tick: 0.,
+            change: EffectChange::Delay {
+                secs: 0.25,
+                feedback: 1.,
+                mix: 0.5,
+            },
+        };
+        assert!(EffectEvent::validate(&[looping]).is_err());
+    }
+
+    #[test]
+    fn bitcrush_only_touches_reduced_depths() {
+        for input in [-1., -0.337, 0., 0.1234567, 0.999] {
+            assert_eq!(crush(input, 16), input);
+        }
+        // at 1 bit a sample can only be silent or at full scale
+        assert_eq!(crush(0.2, 1), 0.);
+        assert_eq!(crush(0.7, 1), 1.);
+        assert_eq!(crush(-0.7, 1), -1.);
+        assert_eq!(crush(0.3, 3), 0.25);
+    }
+
+    #[test]
+    fn validate_rejects_out_of_range_chart_effects() {
+        let event = |tick, change| EffectEvent { tick, change };
+        let valid = [
+            event(0., EffectChange::Cutoff { hz: 800. }),
+            event(4., EffectChange::Resonance { amount: 0.5 }),
+            event(
+                8.,
+                EffectChange::Delay {
+                    secs: 0.5,
+                    feedback: 0.5,
+                    mix: 0.3,
+                },
+            ),
+            event(8., EffectChange::Bitcrush { bits: 1 }),
+        ];
+        assert!(EffectEvent::validate(&valid).is_ok());
+
+        let invalid = [
+            EffectChange::Cutoff { hz: 10. },
+            EffectChange::Cutoff { hz: 30_000. },
+            EffectChange::Resonance { amount: 1.5 },
+            EffectChange::Delay {
+                secs: 2.,
+                feedback: 0.5,
+                mix: 0.3,
+            },
+            EffectChange::Delay {
+                secs: 0.5,
+                feedback: 0.5,
+                mix: -0.1,
+            },
+            EffectChange::Bitcrush { bits: 0 },
+            EffectChange::Bitcrush { bits: 17 },
+        ];
+        for change in invalid {
+            assert!(EffectEvent::validate(&[event(0., change)]).is_err());
+        }
+
+        let out_of_order = [
+            event(4., EffectChange::Cutoff { hz: 800. }),
+            event(0., EffectChange::Cutoff { hz: 400. }),
+        ];
+        assert!(EffectEvent::validate(&out_of_order).is_err());
+    }
+}
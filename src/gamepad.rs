@@ -0,0 +1,190 @@
+use bevy::{
+    input::gamepad::{GamepadConnection, GamepadConnectionEvent},
+    prelude::*,
+    utils::{HashMap, Instant},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{clear_actions, Action, ActionSet, ActionTimes},
+    osc::OscType,
+    pot::PotType,
+    settings::Settings,
+};
+
+/// How far the left stick has to lean before it counts as a direction.
+const STICK_THRESHOLD: f32 = 0.5;
+
+pub(super) struct GamepadPlugin;
+
+impl Plugin for GamepadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (gamepad_connections, gamepad_actions, stick_actions)
+                .chain()
+                .in_set(ActionSet)
+                .after(clear_actions),
+        );
+    }
+}
+
+/// Gamepad button each action is bound to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct PadBindings(Vec<(Action, GamepadButtonType)>);
+
+impl PadBindings {
+    pub(crate) fn button(&self, action: Action) -> Option<GamepadButtonType> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, button)| *button)
+    }
+
+    /// Bind `button` to `action`; whatever held the button before takes the action's old
+    /// button.
+    pub(crate) fn bind(&mut self, action: Action, button: GamepadButtonType) {
+        match self.button(action) {
+            Some(previous) => {
+                for (bound, bound_button) in self.0.iter_mut() {
+                    if *bound_button == button && *bound != action {
+                        *bound_button = previous;
+                    }
+                }
+            }
+            None => self.0.retain(|(_, bound_button)| *bound_button != button),
+        }
+        match self.0.iter_mut().find(|(bound, _)| *bound == action) {
+            Some((_, bound_button)) => *bound_button = button,
+            None => self.0.push((action, button)),
+        }
+    }
+
+    /// Gives actions that saved bindings predate their default button.
+    pub(crate) fn add_missing(&mut self) {
+        for (action, button) in PadBindings::default().0 {
+            if self.button(action).is_none() {
+                self.0.push((action, button));
+            }
+        }
+    }
+}
+
+impl Default for PadBindings {
+    fn default() -> Self {
+        // the d-pad mirrors W/A/S/D; face buttons and the right shoulder take the pots
+        PadBindings(vec![
+            (Action::Osc(OscType::Sine), GamepadButtonType::DPadLeft),
+            (Action::Osc(OscType::Triangle), GamepadButtonType::DPadUp),
+            (Action::Osc(OscType::Square), GamepadButtonType::DPadDown),
+            (Action::Osc(OscType::Sawtooth), GamepadButtonType::DPadRight),
+            (Action::Pot(PotType::PotJ), GamepadButtonType::West),
+            (Action::Pot(PotType::PotI), GamepadButtonType::North),
+            (Action::Pot(PotType::PotK), GamepadButtonType::South),
+            (Action::Pot(PotType::PotO), GamepadButtonType::East),
+            (Action::Pot(PotType::PotL), GamepadButtonType::RightTrigger),
+            (Action::Pause, GamepadButtonType::Start),
+            (Action::Menu, GamepadButtonType::Select),
+            // free mode effects sit on the shoulders and stick clicks, out of the way of
+            // the pots
+            (Action::CutoffUp, GamepadButtonType::RightTrigger2),
+            (Action::CutoffDown, GamepadButtonType::LeftTrigger2),
+            (Action::ResonanceUp, GamepadButtonType::RightThumb),
+            (Action::ResonanceDown, GamepadButtonType::LeftThumb),
+            (Action::DelayMix, GamepadButtonType::LeftTrigger),
+            (Action::BitDepth, GamepadButtonType::Mode),
+            // every standard button is taken, so calibration taps go on the extra face
+            // button some pads have; the controls screen can move it to any other
+            (Action::Tap, GamepadButtonType::C),
+        ])
+    }
+}
+
+fn gamepad_connections(
+    mut ev_connection: EventReader<GamepadConnectionEvent>,
+    mut actions: ResMut<ButtonInput<Action>>,
+) {
+    for ev in ev_connection.read() {
+        match &ev.connection {
+            GamepadConnection::Connected(info) => {
+                info!("gamepad {} connected: {}", ev.gamepad.id, info.name);
+            }
+            GamepadConnection::Disconnected => {
+                info!("gamepad {} disconnected", ev.gamepad.id);
+                // nothing will send the releases for whatever the pad was holding
+                actions.release_all();
+            }
+        }
+    }
+}
+
+fn gamepad_actions(
+    gamepads: Res<Gamepads>,
+    buttons: Res<ButtonInput<GamepadButton>>,
+    settings: Res<Settings>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut times: ResMut<ActionTimes>,
+) {
+    // presses are stamped as they are read, see `ActionTimes`
+    let now = Instant::now();
+    for gamepad in gamepads.iter() {
+        for (action, button_type) in settings.pad_bindings.0.iter() {
+            let button = GamepadButton::new(gamepad, *button_type);
+            if buttons.just_pressed(button) {
+                times.press(&mut actions, *action, now);
+            }
+            if buttons.just_released(button) {
+                actions.release(*action);
+            }
+        }
+    }
+}
+
+fn stick_direction(x: f32, y: f32) -> Option<OscType> {
+    if x.abs().max(y.abs()) < STICK_THRESHOLD {
+        return None;
+    }
+    Some(if x.abs() > y.abs() {
+        if x < 0. {
+            OscType::Sine
+        } else {
+            OscType::Sawtooth
+        }
+    } else if y > 0. {
+        OscType::Triangle
+    } else {
+        OscType::Square
+    })
+}
+
+/// Treats the left stick like the d-pad, one oscillator per direction.
+fn stick_actions(
+    gamepads: Res<Gamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut held: Local<HashMap<Gamepad, OscType>>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut times: ResMut<ActionTimes>,
+) {
+    // presses are stamped as they are read, see `ActionTimes`
+    let now = Instant::now();
+    held.retain(|gamepad, _| gamepads.contains(*gamepad));
+    for gamepad in gamepads.iter() {
+        let axis = |axis_type| axes.get(GamepadAxis::new(gamepad, axis_type)).unwrap_or(0.);
+        let direction = stick_direction(
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        let previous = held.get(&gamepad).copied();
+        if direction == previous {
+            continue;
+        }
+        if let Some(osc) = previous {
+            actions.release(Action::Osc(osc));
+            held.remove(&gamepad);
+        }
+        if let Some(osc) = direction {
+            times.press(&mut actions, Action::Osc(osc), now);
+            held.insert(gamepad, osc);
+        }
+    }
+}
@@ -0,0 +1,488 @@
+//! Headless runs of the whole game for gameplay tests.
+//!
+//! The harness loads `tests/assets/charts/harness.chart.json`, which has no backing song, so
+//! the track clock follows the manual frame time and every run plays out the same way.
+
+use std::time::Duration;
+
+use bevy::{
+    asset::AssetPlugin,
+    audio::{AudioPlugin, Volume},
+    ecs::event::ManualEventReader,
+    input::{
+        keyboard::{Key, KeyboardInput, NativeKey},
+        ButtonState,
+    },
+    prelude::*,
+    state::app::StatesPlugin,
+    time::TimeUpdateStrategy,
+};
+
+use crate::{
+    action::Action,
+    chart::{Chart, ChartHandle},
+    effects::Effects,
+    judgment::{Judgment, JudgmentEvent},
+    rhythm::{Sequencer, Step},
+    settings::Settings,
+    track::{MissEvent, Track, TrackClock},
+    ApplicationState, ModeState, OpticalRacePlugin, PauseState, Score,
+};
+
+const HARNESS_ASSETS: &str = "tests/assets";
+const HARNESS_CHART: &str = "charts/harness.chart.json";
+/// Length of one simulated frame.
+const FRAME: Duration = Duration::from_micros(1_000_000 / 240);
+/// Frames to wait for the chart before giving up.
+const LOAD_FRAMES: usize = 10_000;
+
+/// The game without a window, GPU or audio device, stepped one frame at a time.
+pub(crate) struct Harness {
+    app: App,
+    judgment_reader: ManualEventReader<JudgmentEvent>,
+    miss_reader: ManualEventReader<MissEvent>,
+    /// Every judgment made since the harness started, in order.
+    pub(crate) judgments: Vec<JudgmentEvent>,
+    /// Tick of every note that went unplayed, in order.
+    pub(crate) misses: Vec<u64>,
+}
+
+impl Harness {
+    pub(crate) fn new() -> Self {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            StatesPlugin,
+            AssetPlugin {
+                file_path: HARNESS_ASSETS.into(),
+                ..default()
+            },
+            bevy::input::InputPlugin,
+            AudioPlugin {
+                global_volume: GlobalVolume {
+                    volume: Volume::new(0.),
+                },
+                ..default()
+            },
+        ));
+        // sprites are loaded but never drawn
+        app.init_asset::<Image>();
+        app.add_plugins(OpticalRacePlugin);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(FRAME));
+        // whatever the developer has rebound stays out of the tests
+        app.insert_resource(Settings::default());
+
+        // run startup, then swap the default chart for the harness one
+        app.update();
+        let chart = app.world().resource::<AssetServer>().load(HARNESS_CHART);
+        app.insert_resource(ChartHandle(chart));
+
+        Harness {
+            app,
+            judgment_reader: ManualEventReader::default(),
+            miss_reader: ManualEventReader::default(),
+            judgments: Vec::new(),
+            misses: Vec::new(),
+        }
+    }
+
+    /// Starts the chart from the menu and waits until it is playing.
+    pub(crate) fn start(&mut self) {
+        self.start_in(ModeState::Singleplayer);
+    }
+
+    /// Starts the chart in `mode`, as the menu's buttons do.
+    pub(crate) fn start_in(&mut self, mode: ModeState) {
+        let world = self.app.world_mut();
+        world
+            .resource_mut::<NextState<ApplicationState>>()
+            .set(ApplicationState::Loading);
+        world.resource_mut::<NextState<ModeState>>().set(mode);
+        for _ in 0..LOAD_FRAMES {
+            self.step();
+            if *self.app_state() == ApplicationState::InGame {
+                return;
+            }
+            // the chart loads on another thread
+            std::thread::yield_now();
+        }
+        panic!("chart never loaded");
+    }
+
+    /// Runs one frame.
+    pub(crate) fn step(&mut self) {
+        self.app.update();
+
+        let world = self.app.world();
+        let judgments = world.resource::<Events<JudgmentEvent>>();
+        self.judgments
+            .extend(self.judgment_reader.read(judgments).copied());
+        let misses = world.resource::<Events<MissEvent>>();
+        self.misses
+            .extend(self.miss_reader.read(misses).map(|ev| ev.tick));
+    }
+
+    /// Runs frames until the track clock reaches `secs`.
+    pub(crate) fn run_until(&mut self, secs: f64) {
+        while self.clock_secs() < secs {
+            self.step();
+        }
+    }
+
+    /// Runs frames until the next one starts within half a frame of `secs`.
+    pub(crate) fn run_to(&mut self, secs: f64) {
+        self.run_until(secs - FRAME.as_secs_f64() * 1.5);
+    }
+
+    /// Runs frames for `secs` of simulated time.
+    pub(crate) fn advance(&mut self, secs: f64) {
+        let frames = (secs / FRAME.as_secs_f64()).ceil() as usize;
+        for _ in 0..frames {
+            self.step();
+        }
+    }
+
+    /// Presses `key` at the start of the next frame.
+    pub(crate) fn press(&mut self, key: KeyCode) {
+        self.send_key(key, ButtonState::Pressed);
+    }
+
+    pub(crate) fn release(&mut self, key: KeyCode) {
+        self.send_key(key, ButtonState::Released);
+    }
+
+    /// Presses every key in `keys` on the same frame, then lets go a frame later.
+    pub(crate) fn tap(&mut self, keys: &[KeyCode]) {
+        for key in keys {
+            self.press(*key);
+        }
+        self.step();
+        for key in keys {
+            self.release(*key);
+        }
+        self.step();
+    }
+
+    fn send_key(&mut self, key_code: KeyCode, state: ButtonState) {
+        self.app.world_mut().send_event(KeyboardInput {
+            key_code,
+            logical_key: Key::Unidentified(NativeKey::Unidentified),
+            state,
+            window: Entity::PLACEHOLDER,
+        });
+    }
+
+    /// Reloads the chart as if its file had been saved, without changing it.
+    pub(crate) fn reload_chart(&mut self) {
+        let world = self.app.world_mut();
+        let handle = world.resource::<ChartHandle>().0.clone();
+        // touching the asset mutably is what marks it modified
+        world.resource_mut::<Assets<Chart>>().get_mut(&handle);
+    }
+
+    /// Track time in seconds as of the start of the last frame.
+    pub(crate) fn clock_secs(&self) -> f64 {
+        let world = self.app.world();
+        let pending = world.resource::<Time<Virtual>>().elapsed_seconds_f64()
+            - world.resource::<Time<Fixed>>().elapsed_seconds_f64();
+        world.resource::<TrackClock>().elapsed_secs() + pending
+    }
+
+    /// Track time as of the last fixed tick.
+    pub(crate) fn track_clock_secs(&self) -> f64 {
+        self.app.world().resource::<TrackClock>().elapsed_secs()
+    }
+
+    pub(crate) fn score(&self) -> &Score {
+        self.app.world().resource::<Score>()
+    }
+
+    pub(crate) fn track(&self) -> &Track {
+        self.app.world().resource::<Track>()
+    }
+
+    pub(crate) fn sequencer(&self) -> &Sequencer {
+        &self.track().sequencer
+    }
+
+    pub(crate) fn app_state(&self) -> &ApplicationState {
+        self.app.world().resource::<State<ApplicationState>>().get()
+    }
+
+    pub(crate) fn mode_state(&self) -> &ModeState {
+        self.app.world().resource::<State<ModeState>>().get()
+    }
+
+    pub(crate) fn effects(&self) -> &Effects {
+        self.app.world().resource::<Effects>()
+    }
+
+    pub(crate) fn pause_state(&self) -> &PauseState {
+        self.app.world().resource::<State<PauseState>>().get()
+    }
+
+    fn judged(&self) -> Vec<Judgment> {
+        self.judgments.iter().map(|ev| ev.judgment).collect()
+    }
+}
+
+/// Keys for the oscillators and pot of the step at `index`.
+fn note_keys(track: &Track, index: usize) -> Vec<KeyCode> {
+    let settings = Settings::default();
+    let note = &track.seq[index].note;
+    note.oscs()
+        .map(Action::Osc)
+        .chain(std::iter::once(Action::Pot(note.pot)))
+        .filter_map(|action| settings.bindings.key(action))
+        .collect()
+}
+
+/// Plays the step at `index` during loop `iteration`, `late` seconds after the note.
+fn play_note(harness: &mut Harness, index: usize, iteration: u64, late: f64) {
+    let secs = harness.sequencer().note_secs(Step::new(index, iteration)) + late;
+    let keys = note_keys(harness.track(), index);
+    harness.run_to(secs);
+    harness.tap(&keys);
+}
+
+#[test]
+fn chart_loads_and_starts_at_the_top() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    let track = harness.track();
+    assert_eq!(track.seq.len(), 3);
+    assert_eq!(track.sequencer.steps(), 4);
+    assert_eq!(track.audio, None);
+    assert_eq!(track.sequencer.current(), Step::new(0, 0));
+    assert_eq!(harness.score().value, 0);
+}
+
+#[test]
+fn on_time_presses_are_perfect() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    for index in 0..3 {
+        play_note(&mut harness, index, 0, 0.);
+    }
+
+    assert_eq!(harness.judged(), vec![Judgment::Perfect; 3]);
+    assert!(harness
+        .judgments
+        .iter()
+        .all(|ev| ev.offset.abs() < FRAME.as_secs_f64()));
+    assert_eq!(harness.score().value, 3 * Judgment::Perfect.points());
+    assert_eq!(harness.score().combo, 3);
+    assert!(harness.misses.is_empty());
+}
+
+#[test]
+fn late_presses_are_judged_by_their_offset() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    play_note(&mut harness, 0, 0, 0.07);
+    play_note(&mut harness, 1, 0, 0.12);
+
+    assert_eq!(harness.judged(), vec![Judgment::Great, Judgment::Good]);
+    assert!(harness.judgments.iter().all(|ev| ev.offset > 0.));
+    assert_eq!(
+        harness.score().value,
+        Judgment::Great.points() + Judgment::Good.points()
+    );
+}
+
+#[test]
+fn key_presses_are_timed_from_the_start_of_their_frame() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    let note = harness.sequencer().note_secs(Step::new(0, 0));
+    let keys = note_keys(harness.track(), 0);
+    harness.run_to(note);
+    for key in &keys {
+        harness.press(*key);
+    }
+    harness.step();
+
+    // however long the frame took to reach input, the press lands where the frame began
+    let pressed_at = harness.clock_secs();
+    assert_eq!(harness.judgments.len(), 1);
+    assert!((harness.judgments[0].offset - (pressed_at - note)).abs() < 1e-9);
+}
+
+#[test]
+fn early_presses_are_judged_before_the_track_reaches_the_note() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    play_note(&mut harness, 0, 0, 0.);
+    play_note(&mut harness, 1, 0, -0.06);
+
+    // the fixed loop was still on the first step when the second was pressed
+    assert_eq!(harness.judged(), vec![Judgment::Perfect, Judgment::Great]);
+    assert!(harness.judgments[1].offset < 0.);
+    assert!(harness.misses.is_empty());
+}
+
+#[test]
+fn wrong_oscillator_is_a_miss() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    let secs = harness.sequencer().note_secs(Step::new(0, 0));
+    harness.run_to(secs);
+    harness.tap(&[KeyCode::KeyS, KeyCode::KeyJ]);
+
+    assert_eq!(harness.judged(), vec![Judgment::Miss]);
+    assert_eq!(harness.score().value, 0);
+}
+
+#[test]
+fn unplayed_notes_are_missed_and_break_the_combo() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    play_note(&mut harness, 0, 0, 0.);
+    assert_eq!(harness.score().combo, 1);
+
+    // a note is only counted missed once its timing window has closed
+    let second = harness.sequencer().note_secs(Step::new(1, 0));
+    harness.run_until(second + 0.2);
+
+    assert_eq!(harness.judged(), vec![Judgment::Perfect, Judgment::Miss]);
+    assert_eq!(
+        harness.misses,
+        vec![harness.sequencer().note_tick(Step::new(1, 0))]
+    );
+    assert_eq!(harness.score().combo, 0);
+    assert_eq!(harness.score().max_combo, 1);
+}
+
+#[test]
+fn track_steps_through_the_sequence_and_loops() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    let sequencer = harness.sequencer();
+    let second = sequencer.note_secs(Step::new(1, 0));
+    let next_loop = sequencer.note_secs(Step::new(0, 1));
+
+    harness.advance(second + 0.01);
+    assert_eq!(harness.sequencer().current(), Step::new(1, 0));
+
+    harness.run_until(next_loop + 0.01);
+    assert_eq!(harness.sequencer().current(), Step::new(0, 1));
+}
+
+#[test]
+fn song_ends_on_the_results_screen() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    for iteration in 0..2 {
+        for index in 0..3 {
+            play_note(&mut harness, index, iteration, 0.);
+        }
+    }
+    harness.advance(1.);
+
+    assert!(harness.sequencer().finished());
+    assert_eq!(*harness.app_state(), ApplicationState::Results);
+    assert_eq!(harness.judged(), vec![Judgment::Perfect; 6]);
+    assert_eq!(harness.score().max_combo, 6);
+}
+
+#[test]
+fn a_finished_song_cannot_be_resumed() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    for iteration in 0..2 {
+        for index in 0..3 {
+            play_note(&mut harness, index, iteration, 0.);
+        }
+    }
+    harness.advance(1.);
+    assert_eq!(*harness.app_state(), ApplicationState::Results);
+
+    harness.tap(&[KeyCode::Escape]);
+    harness.step();
+    assert_eq!(*harness.app_state(), ApplicationState::Menu);
+    harness.tap(&[KeyCode::Escape]);
+    for _ in 0..10 {
+        harness.step();
+    }
+    assert_eq!(*harness.app_state(), ApplicationState::Menu);
+}
+
+#[test]
+fn free_mode_effects_follow_their_controls() {
+    let mut harness = Harness::new();
+    harness.start_in(ModeState::Freeform);
+    harness.step();
+    assert_eq!(*harness.mode_state(), ModeState::Freeform);
+
+    let open = harness.effects().cutoff;
+    harness.press(KeyCode::ArrowDown);
+    harness.advance(0.25);
+    harness.release(KeyCode::ArrowDown);
+    harness.tap(&[KeyCode::Digit2]);
+
+    assert!(harness.effects().cutoff < open);
+    assert_eq!(harness.effects().bits, 8);
+}
+
+#[test]
+fn reloading_the_chart_mid_song_keeps_what_was_played() {
+    let mut harness = Harness::new();
+    harness.start();
+
+    for index in 0..3 {
+        play_note(&mut harness, index, 0, 0.);
+    }
+    play_note(&mut harness, 0, 1, 0.);
+    harness.reload_chart();
+    harness.advance(0.1);
+
+    assert!(harness.misses.is_empty());
+    assert_eq!(harness.judged(), vec![Judgment::Perfect; 4]);
+    play_note(&mut harness, 1, 1, 0.);
+    assert_eq!(harness.judged(), vec![Judgment::Perfect; 5]);
+}
+
+#[test]
+fn pausing_stops_the_track_clock() {
+    let mut harness = Harness::new();
+    harness.start();
+    let first = harness.sequencer().note_secs(Step::new(0, 0));
+    harness.run_until(first - 0.05);
+
+    harness.tap(&[KeyCode::Backquote]);
+    // the pause goes through an event and a state transition before it lands
+    harness.step();
+    assert_eq!(*harness.pause_state(), PauseState::Paused);
+    let paused_at = harness.track_clock_secs();
+
+    // close enough to the first note to be judged, were the game running
+    let keys = note_keys(harness.track(), 0);
+    harness.tap(&keys);
+    harness.advance(1.);
+    assert_eq!(harness.track_clock_secs(), paused_at);
+    assert!(harness.judgments.is_empty());
+    assert!(harness.misses.is_empty());
+}
+
+#[test]
+fn menu_key_does_nothing_before_a_chart_is_loaded() {
+    let mut harness = Harness::new();
+
+    harness.tap(&[KeyCode::Escape]);
+    for _ in 0..10 {
+        harness.step();
+    }
+
+    assert_eq!(*harness.app_state(), ApplicationState::Menu);
+    assert!(!harness.track().is_loaded());
+}
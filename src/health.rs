@@ -0,0 +1,138 @@
+use bevy::{
+    color::palettes::css::{DARK_SLATE_GRAY, ORANGE},
+    prelude::*,
+    sprite::Anchor,
+};
+
+use crate::{
+    judgment::{Judgment, JudgmentEvent},
+    ApplicationState, ModeState,
+};
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct HealthSet;
+
+pub(super) struct HealthPlugin;
+
+impl Plugin for HealthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            OnExit(ApplicationState::Loading),
+            (reset_health, load_health_gauge).in_set(HealthSet),
+        );
+        app.add_systems(
+            Update,
+            (drain_health, check_failed, health_display)
+                .chain()
+                .in_set(HealthSet)
+                .run_if(in_state(ApplicationState::InGame))
+                .run_if(in_state(ModeState::Singleplayer)),
+        );
+        app.add_systems(OnEnter(ModeState::NotInGame), unload_health_gauge);
+        app.init_resource::<Health>();
+    }
+}
+
+/// Life remaining in the current run, between 0 and 1.
+#[derive(Resource)]
+pub(crate) struct Health {
+    pub(crate) value: f32,
+}
+
+impl Default for Health {
+    fn default() -> Self {
+        Health { value: 0.5 }
+    }
+}
+
+impl Health {
+    fn change(judgment: Judgment) -> f32 {
+        match judgment {
+            Judgment::Perfect => 0.03,
+            Judgment::Great => 0.02,
+            Judgment::Good => 0.01,
+            Judgment::Miss => -0.1,
+        }
+    }
+}
+
+fn reset_health(mut health: ResMut<Health>) {
+    *health = Health::default();
+}
+
+fn drain_health(mut ev_judgment: EventReader<JudgmentEvent>, mut health: ResMut<Health>) {
+    for ev in ev_judgment.read() {
+        health.value = (health.value + Health::change(ev.judgment)).clamp(0., 1.);
+    }
+}
+
+fn check_failed(health: Res<Health>, mut next_app_state: ResMut<NextState<ApplicationState>>) {
+    if health.value <= 0. {
+        next_app_state.set(ApplicationState::Failed);
+    }
+}
+
+const GAUGE_WIDTH: f32 = 200.;
+const GAUGE_HEIGHT: f32 = 12.;
+
+#[derive(Component)]
+struct HealthGaugeTag;
+
+#[derive(Component)]
+struct HealthFillTag;
+
+fn load_health_gauge(
+    mut commands: Commands,
+    query: Query<Entity, With<HealthGaugeTag>>,
+    mode: Res<State<ModeState>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+    if mode.get() != &ModeState::Singleplayer {
+        return;
+    }
+
+    let origin = Vec3::new(-300., 228., 100.);
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::Srgba(DARK_SLATE_GRAY),
+                custom_size: Some(Vec2::new(GAUGE_WIDTH, GAUGE_HEIGHT)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform: Transform::from_translation(origin),
+            ..default()
+        },
+        HealthGaugeTag,
+    ));
+    commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: Color::Srgba(ORANGE),
+                custom_size: Some(Vec2::new(GAUGE_WIDTH, GAUGE_HEIGHT)),
+                anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            transform: Transform::from_translation(origin + Vec3::Z),
+            ..default()
+        },
+        HealthGaugeTag,
+        HealthFillTag,
+    ));
+}
+
+fn health_display(health: Res<Health>, mut query: Query<&mut Sprite, With<HealthFillTag>>) {
+    if health.is_changed() {
+        for mut sprite in query.iter_mut() {
+            sprite.custom_size = Some(Vec2::new(GAUGE_WIDTH * health.value, GAUGE_HEIGHT));
+        }
+    }
+}
+
+fn unload_health_gauge(mut commands: Commands, query: Query<Entity, With<HealthGaugeTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
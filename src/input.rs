@@ -1,6 +1,6 @@
 use bevy::prelude::*;
 
-use crate::{ApplicationState, PauseState};
+use crate::{action::Action, track::Track, ApplicationState, PauseState};
 
 pub(super) struct InputPlugin;
 
@@ -15,17 +15,15 @@ impl Plugin for InputPlugin {
 }
 
 fn game_input(
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ButtonInput<Action>>,
     mut ev_pause: EventWriter<PauseEvent>,
     mut ev_menu: EventWriter<MenuEvent>,
 ) {
-    for key in keys.get_just_pressed() {
-        if key == &KeyCode::Backquote {
-            ev_pause.send(PauseEvent);
-        }
-        if key == &KeyCode::Escape {
-            ev_menu.send(MenuEvent);
-        }
+    if actions.just_pressed(Action::Pause) {
+        ev_pause.send(PauseEvent);
+    }
+    if actions.just_pressed(Action::Menu) {
+        ev_menu.send(MenuEvent);
     }
 }
 
@@ -40,8 +38,8 @@ fn pause(
 ) {
     for _ev in ev_pause.read() {
         match state.get() {
-            PauseState::Unpaused => next_state.set(PauseState::Paused),
-            PauseState::Paused => next_state.set(PauseState::Unpaused),
+            PauseState::Unpaused | PauseState::Resuming => next_state.set(PauseState::Paused),
+            PauseState::Paused => next_state.set(PauseState::Resuming),
         }
     }
 }
@@ -52,10 +50,13 @@ struct MenuEvent;
 fn menu(
     mut ev_menu: EventReader<MenuEvent>,
     state: Res<State<ApplicationState>>,
+    track: Res<Track>,
     mut next_state: ResMut<NextState<ApplicationState>>,
 ) {
     for _ev in ev_menu.read() {
         match state.get() {
+            // there's no game to go back to until a chart has been loaded
+            ApplicationState::Menu if !track.is_loaded() => {}
             ApplicationState::Menu => next_state.set(ApplicationState::InGame),
             _ => next_state.set(ApplicationState::Menu),
         }
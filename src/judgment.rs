@@ -0,0 +1,122 @@
+use bevy::{prelude::*, sprite::Anchor};
+
+pub(crate) use crate::rhythm::{Judgment, JudgmentStats, TimingWindows};
+use crate::{ApplicationState, Score};
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub(super) struct JudgmentSet;
+
+pub(super) struct JudgmentPlugin;
+
+impl Plugin for JudgmentPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnExit(ApplicationState::Loading), reset_stats);
+        app.add_systems(
+            Update,
+            (apply_judgments, judgment_display, fade_judgment_display)
+                .in_set(JudgmentSet)
+                .run_if(in_state(ApplicationState::InGame)),
+        );
+        app.init_resource::<TimingWindows>();
+        app.init_resource::<JudgmentStats>();
+        app.add_event::<JudgmentEvent>();
+        app.add_event::<HoldEvent>();
+    }
+}
+
+// the rules themselves live in `rhythm`, clear of the engine
+impl Resource for TimingWindows {}
+
+impl Resource for JudgmentStats {}
+
+impl Judgment {
+    fn label(self) -> &'static str {
+        match self {
+            Judgment::Perfect => "PERFECT",
+            Judgment::Great => "GREAT",
+            Judgment::Good => "GOOD",
+            Judgment::Miss => "MISS",
+        }
+    }
+}
+
+#[derive(Event, Clone, Copy, Debug)]
+pub(crate) struct JudgmentEvent {
+    pub(crate) judgment: Judgment,
+    /// Seconds between the press and the note; negative when early.
+    pub(crate) offset: f64,
+}
+
+/// Sent when a hold note ends, either completed or released early.
+#[derive(Event, Clone, Copy, Debug)]
+pub(crate) struct HoldEvent {
+    /// Fraction of the hold that was sustained, between 0 and 1.
+    pub(crate) completion: f64,
+}
+
+fn reset_stats(mut stats: ResMut<JudgmentStats>) {
+    *stats = JudgmentStats::default();
+}
+
+fn apply_judgments(
+    mut ev_judgment: EventReader<JudgmentEvent>,
+    mut ev_hold: EventReader<HoldEvent>,
+    mut stats: ResMut<JudgmentStats>,
+    mut score: ResMut<Score>,
+) {
+    for ev in ev_judgment.read() {
+        debug!("{:?} ({:+.0} ms)", ev.judgment, ev.offset * 1000.);
+        stats.record(ev.judgment);
+        score.record(ev.judgment);
+    }
+    for ev in ev_hold.read() {
+        debug!("hold {:.0}%", ev.completion * 100.);
+        score.record_hold(ev.completion);
+    }
+}
+
+#[derive(Component)]
+struct JudgmentDispTag {
+    timer: Timer,
+}
+
+fn judgment_display(
+    mut commands: Commands,
+    mut ev_judgment: EventReader<JudgmentEvent>,
+    query: Query<Entity, With<JudgmentDispTag>>,
+) {
+    if let Some(ev) = ev_judgment.read().last() {
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        commands.spawn((
+            Text2dBundle {
+                text: Text::from_section(
+                    ev.judgment.label(),
+                    TextStyle {
+                        font_size: 24.,
+                        ..default()
+                    },
+                ),
+                transform: Transform::from_translation(Vec3::new(16., 228., 104.)),
+                text_anchor: Anchor::CenterLeft,
+                ..default()
+            },
+            JudgmentDispTag {
+                timer: Timer::from_seconds(0.5, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+fn fade_judgment_display(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut JudgmentDispTag)>,
+) {
+    for (entity, mut disp) in query.iter_mut() {
+        if disp.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
+}
@@ -1,8 +1,11 @@
-use std::time::Duration;
-
 use bevy::prelude::*;
 
-use crate::{pot::CheckNoteEvent, ApplicationState, ModeState};
+use crate::{
+    chart::ChartReloadedEvent,
+    pot::CheckNoteEvent,
+    track::{step_transform, Track, TrackClock},
+    ApplicationState, ModeState,
+};
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub(super) struct LedSet;
@@ -11,72 +14,38 @@ pub(super) struct LedPlugin;
 
 impl Plugin for LedPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(ApplicationState::Loading), load_leds);
+        app.add_systems(OnEnter(ApplicationState::Loading), unload_leds);
+        app.add_systems(OnExit(ApplicationState::Loading), load_leds);
         app.add_systems(OnEnter(ApplicationState::Freeform), load_leds);
+        app.add_systems(FixedUpdate, tick_leds.in_set(LedSet));
         app.add_systems(
-            OnEnter(ApplicationState::InGame),
-            init_led_timer.in_set(LedSet),
-        );
-        app.add_systems(
-            OnEnter(ApplicationState::Freeform),
-            init_led_timer.in_set(LedSet),
+            Update,
+            reload_leds.run_if(in_state(ApplicationState::InGame)),
         );
-        app.add_systems(FixedUpdate, tick_leds.in_set(LedSet));
         app.add_systems(OnEnter(ModeState::NotInGame), unload_leds);
     }
 }
 
-#[derive(Component)]
-struct LedTick {
-    timer: Timer,
-    next_led: LedPos,
-}
-
-fn init_led_timer(mut commands: Commands, query: Query<Entity, With<LedTick>>) {
-    if query.is_empty() {
-        let timer = LedTick {
-            timer: Timer::new(Duration::from_millis(333), TimerMode::Repeating),
-            next_led: LedPos::B,
-        };
-
-        println!("timer spawned");
-        commands.spawn(timer);
-    }
-}
-
 fn tick_leds(
     server: Res<AssetServer>,
-    mut timer_query: Query<&mut LedTick>,
     mut query: Query<(&mut LedState, &LedPos, &mut Handle<Image>)>,
-    time: Res<Time>,
+    clock: Res<TrackClock>,
+    track: Res<Track>,
     mut _ev_check_note: EventWriter<CheckNoteEvent>,
 ) {
-    for mut tick in timer_query.iter_mut() {
-        tick.timer.tick(time.delta());
-        if tick.timer.finished() {
-            for (mut state, pos, mut tex) in query.iter_mut() {
-                let on_tex = server.load("led_on.png");
-                let off_tex = server.load("led_off.png");
-                if *state == LedState::On {
-                    *state = LedState::Off;
-                    *tex = off_tex;
-                }
-                if *pos == tick.next_led {
-                    *state = LedState::On;
-                    *tex = on_tex;
-                    // ev_check_note.send(CheckNoteEvent(*pos)); // check if there is an associated track setting with this
-                }
-            }
-            tick.next_led = match tick.next_led {
-                LedPos::A => LedPos::B,
-                LedPos::B => LedPos::C,
-                LedPos::C => LedPos::D,
-                LedPos::D => LedPos::E,
-                LedPos::E => LedPos::F,
-                LedPos::F => LedPos::G,
-                LedPos::G => LedPos::H,
-                LedPos::H => LedPos::A,
-            };
+    let steps = track.sequencer.steps();
+    if steps == 0 {
+        return;
+    }
+    let lit = LedPos(track.sequencer.tick_at(clock.elapsed_secs()) % steps);
+    for (mut state, pos, mut tex) in query.iter_mut() {
+        if *pos == lit && *state == LedState::Off {
+            *state = LedState::On;
+            *tex = server.load("led_on.png");
+            // ev_check_note.send(CheckNoteEvent(*pos)); // check if there is an associated track setting with this
+        } else if *pos != lit && *state == LedState::On {
+            *state = LedState::Off;
+            *tex = server.load("led_off.png");
         }
     }
 }
@@ -89,117 +58,48 @@ struct LedBundle {
     tag: LedTag,
 }
 
-fn load_leds(mut commands: Commands, server: Res<AssetServer>) {
-    let origin_x = -150.;
+fn load_leds(mut commands: Commands, server: Res<AssetServer>, track: Res<Track>) {
+    spawn_leds(&mut commands, &server, track.sequencer.steps());
+}
+
+fn reload_leds(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    track: Res<Track>,
+    mut ev_reloaded: EventReader<ChartReloadedEvent>,
+    query: Query<Entity, With<LedTag>>,
+) {
+    if ev_reloaded.read().last().is_some() {
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        spawn_leds(&mut commands, &server, track.sequencer.steps());
+    }
+}
+
+fn spawn_leds(commands: &mut Commands, server: &AssetServer, steps: u64) {
     let origin_y = 100.;
-    let offset = 32.;
 
     let tex = server.load("led_off.png");
     let on_tex = server.load("led_on.png");
 
-    let led_a = LedBundle {
-        state: LedState::On,
-        pos: LedPos::A,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x, origin_y, 0.)),
-
-            texture: on_tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_a);
-
-    let led_b = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::B,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 1., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_b);
-
-    let led_c = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::C,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 2., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_c);
-
-    let led_d = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::D,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 3., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_d);
-
-    let led_e = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::E,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 4., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_e);
-
-    let led_f = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::F,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 5., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_f);
-
-    let led_g = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::G,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 6., origin_y, 0.)),
-
-            texture: tex.clone(),
-            ..default()
-        },
-    };
-    commands.spawn(led_g);
-
-    let led_h = LedBundle {
-        state: LedState::Off,
-        pos: LedPos::H,
-        tag: LedTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + offset * 7., origin_y, 0.)),
-
-            texture: tex,
-            ..default()
-        },
-    };
-    commands.spawn(led_h);
+    for column in 0..steps {
+        let (state, texture) = if column == 0 {
+            (LedState::On, on_tex.clone())
+        } else {
+            (LedState::Off, tex.clone())
+        };
+        commands.spawn(LedBundle {
+            state,
+            pos: LedPos(column),
+            tag: LedTag,
+            sprite: SpriteBundle {
+                transform: step_transform(column, steps, origin_y, 0.),
+                texture,
+                ..default()
+            },
+        });
+    }
 }
 
 fn unload_leds(mut commands: Commands, query: Query<Entity, With<LedTag>>) {
@@ -218,13 +118,4 @@ enum LedState {
 }
 
 #[derive(Component, PartialEq, Debug, Copy, Clone)]
-pub(crate) enum LedPos {
-    A,
-    B,
-    C,
-    D,
-    E,
-    F,
-    G,
-    H,
-}
+pub(crate) struct LedPos(u64);
@@ -1,22 +1,52 @@
 use bevy::prelude::*;
 // use bevy_console::ConsoleCommand;
 // use clap::Parser;
+use action::ActionPlugin;
+use calibration::CalibrationPlugin;
+use chart::ChartPlugin;
+use effects::EffectsPlugin;
+use gamepad::GamepadPlugin;
+use health::HealthPlugin;
 use input::{InputPlugin, InputSet};
+use judgment::{Judgment, JudgmentPlugin};
 use led::{LedPlugin, LedSet};
 use loading::LoadingPlugin;
 use menu::{MenuPlugin, MenuSet, PauseSet};
+use midi::MidiPlugin;
 use osc::{OscPlugin, OscSet};
 // use player::{PlayerPlugin, PlayerSet};
+use pointer::PointerPlugin;
 use pot::{PotPlugin, PotSet};
+use rhythm::Tally;
+use settings::SettingsPlugin;
+use song::SongPlugin;
+use synth::SynthPlugin;
 use track::{TrackPlugin, TrackSet};
 
+mod action;
+mod calibration;
+mod chart;
+mod effects;
+mod gamepad;
+#[cfg(test)]
+mod harness;
+mod health;
 mod input;
+mod judgment;
 mod loading;
 mod menu;
+mod midi;
 mod osc;
 // mod player;
 mod led;
+mod pointer;
 mod pot;
+mod rhythm;
+mod scale;
+mod settings;
+mod song;
+mod synth;
+mod tempo;
 mod track;
 
 pub struct OpticalRacePlugin;
@@ -73,13 +103,24 @@ impl Plugin for OpticalRacePlugin {
                 LedSet
                     .run_if(in_state(ApplicationState::InGame))
                     // .run_if(in_state(ApplicationState::Freeform))
-                    .run_if(in_state(PauseState::Unpaused)),
+                    .run_if(in_state(PauseState::Unpaused))
+                    .after(TrackSet),
                 TrackSet
                     .run_if(in_state(ApplicationState::InGame))
                     // .run_if(in_state(ApplicationState::Freeform))
                     .run_if(in_state(PauseState::Unpaused)),
+                PotSet
+                    .run_if(in_state(ApplicationState::InGame))
+                    .run_if(in_state(PauseState::Unpaused)),
             ),
         );
+        // presses are judged in PreUpdate, so they have to stop with the track
+        app.configure_sets(
+            PreUpdate,
+            PotSet
+                .run_if(in_state(ApplicationState::InGame))
+                .run_if(in_state(PauseState::Unpaused)),
+        );
         // app.configure_sets(
         //     FixedUpdate,
         //     (
@@ -103,8 +144,8 @@ impl Plugin for OpticalRacePlugin {
         // app.insert_resource(ResourceStruct {})
         // app.insert_resource(Time::<Fixed>::from_hz(64.0));
         app.insert_resource(Score {
-            value: 0,
             updated: true,
+            ..default()
         });
 
         // plugins
@@ -113,25 +154,61 @@ impl Plugin for OpticalRacePlugin {
             MenuPlugin,
             InputPlugin,
             LoadingPlugin,
+            ChartPlugin,
             OscPlugin,
             PotPlugin,
             LedPlugin,
             TrackPlugin,
+            JudgmentPlugin,
+            HealthPlugin,
+        ));
+        app.add_plugins((
+            SynthPlugin,
+            EffectsPlugin,
+            SettingsPlugin,
+            CalibrationPlugin,
+            SongPlugin,
+            ActionPlugin,
+            GamepadPlugin,
+            MidiPlugin,
+            PointerPlugin,
         ));
 
         // systems
+        app.add_systems(OnExit(ApplicationState::Loading), reset_score);
         app.add_systems(OnEnter(ApplicationState::Exit), exit_game);
 
         // console comands
     }
 }
 
-#[derive(Resource)]
+/// Score for the current run, with whether the display is out of date.
+#[derive(Resource, Default, Deref, DerefMut)]
 pub(crate) struct Score {
-    pub(crate) value: u64,
+    #[deref]
+    pub(crate) tally: Tally,
     pub(crate) updated: bool,
 }
 
+impl Score {
+    pub(crate) fn record(&mut self, judgment: Judgment) {
+        self.tally.record(judgment);
+        self.updated = true;
+    }
+
+    pub(crate) fn record_hold(&mut self, completion: f64) {
+        self.tally.record_hold(completion);
+        self.updated = true;
+    }
+}
+
+fn reset_score(mut score: ResMut<Score>) {
+    *score = Score {
+        updated: true,
+        ..default()
+    };
+}
+
 #[derive(Component)]
 struct ScoreDispTag;
 
@@ -145,7 +222,12 @@ fn score_display(
         for entity in query.iter() {
             commands.entity(entity).despawn();
         }
-        let score_disp = format!("SCORE: {}", score.value);
+        let score_disp = format!(
+            "SCORE: {}\nCOMBO: {} x{}",
+            score.value,
+            score.combo,
+            score.multiplier()
+        );
         commands.spawn((
             Text2dBundle {
                 text: Text::from_section(
@@ -197,6 +279,10 @@ pub enum ApplicationState {
     Loading,
     Menu,
     InGame,
+    Failed,
+    Results,
+    Calibration,
+    Controls,
     Exit,
     Freeform,
 }
@@ -214,4 +300,6 @@ pub enum PauseState {
     #[default]
     Unpaused,
     Paused,
+    /// Counting back in before play picks up again.
+    Resuming,
 }
@@ -38,11 +38,15 @@ fn loading_display(mut commands: Commands) {
 fn loading_clear(
     mut commands: Commands,
     mut query: Query<Entity, With<Text>>,
+    mode_state: Res<State<ModeState>>,
     mut next_mode_state: ResMut<NextState<ModeState>>,
 ) {
     for entity in query.iter_mut() {
         commands.entity(entity).despawn();
     }
 
-    next_mode_state.set(ModeState::Singleplayer);
+    // free mode and retries keep the mode they were started in
+    if *mode_state.get() == ModeState::NotInGame {
+        next_mode_state.set(ModeState::Singleplayer);
+    }
 }
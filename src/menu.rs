@@ -1,9 +1,17 @@
 use bevy::{
     color::palettes::css::{BLACK, DARK_SEA_GREEN, LAVENDER},
     prelude::*,
+    ui::UiSystem,
 };
 
-use crate::{ApplicationState, ModeState, PauseState};
+use crate::{
+    action::Action,
+    judgment::JudgmentStats,
+    midi::MidiControl,
+    settings::Settings,
+    track::{Track, TrackClock},
+    ApplicationState, ModeState, PauseState, Score,
+};
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub(super) struct MenuSet;
@@ -18,14 +26,43 @@ impl Plugin for MenuPlugin {
         app.add_systems(OnEnter(ApplicationState::Menu), menu_setup.in_set(MenuSet))
             .add_systems(Update, (game_menu, interact_game_menu).in_set(MenuSet))
             .add_systems(OnExit(ApplicationState::Menu), clear_menu.in_set(MenuSet));
+        app.add_systems(OnEnter(ApplicationState::Failed), fail_setup)
+            .add_systems(OnEnter(ApplicationState::Results), results_setup)
+            .add_systems(
+                Update,
+                interact_end_menu.run_if(
+                    in_state(ApplicationState::Failed).or_else(in_state(ApplicationState::Results)),
+                ),
+            )
+            .add_systems(OnExit(ApplicationState::Failed), clear_menu)
+            .add_systems(OnExit(ApplicationState::Results), clear_menu);
+        app.add_systems(OnEnter(ApplicationState::Controls), controls_setup)
+            .add_systems(
+                Update,
+                (interact_controls, capture_rebind, controls_display)
+                    .chain()
+                    .run_if(in_state(ApplicationState::Controls)),
+            )
+            .add_systems(OnExit(ApplicationState::Controls), clear_menu);
+        app.init_resource::<Rebinding>();
+        app.add_systems(PreUpdate, gamepad_menu_nav.after(UiSystem::Focus));
+        app.init_resource::<PadFocus>();
         app.add_systems(OnEnter(PauseState::Paused), pause_screen.in_set(PauseSet))
             .add_systems(OnExit(PauseState::Paused), clear_pause.in_set(PauseSet));
+        app.add_systems(OnEnter(PauseState::Resuming), count_in_setup)
+            .add_systems(Update, count_in.run_if(in_state(PauseState::Resuming)))
+            .add_systems(OnExit(PauseState::Resuming), clear_pause);
+        // a new run never starts paused
+        app.add_systems(OnEnter(ApplicationState::Loading), unpause);
     }
 }
 
 #[derive(Component)]
 enum MenuLayer {
     Main,
+    Failed,
+    Results,
+    Controls,
 }
 
 #[derive(Component)]
@@ -34,6 +71,8 @@ enum MenuOptions {
     Resume,
     Exit,
     FreeMode,
+    Calibrate,
+    Controls,
 }
 
 fn menu_setup(mut commands: Commands, _server: Res<AssetServer>) {
@@ -138,6 +177,62 @@ fn menu_setup(mut commands: Commands, _server: Res<AssetServer>) {
                         },
                     ));
                 });
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(150.0),
+                            height: Val::Px(65.0),
+                            border: UiRect::all(Val::Px(5.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        border_color: BorderColor(Color::Srgba(DARK_SEA_GREEN)),
+                        background_color: BackgroundColor(Color::Srgba(LAVENDER)),
+                        ..default()
+                    },
+                    MenuOptions::Calibrate,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Button",
+                        TextStyle {
+                            // font: server.load("fonts/TitilliumWeb-SemiBold.ttf"),
+                            font_size,
+                            color: Srgba::rgb(0.1, 0.1, 0.1).into(),
+                            ..default()
+                        },
+                    ));
+                });
+            parent
+                .spawn((
+                    ButtonBundle {
+                        style: Style {
+                            width: Val::Px(150.0),
+                            height: Val::Px(65.0),
+                            border: UiRect::all(Val::Px(5.0)),
+                            justify_content: JustifyContent::Center,
+                            align_items: AlignItems::Center,
+                            ..default()
+                        },
+                        border_color: BorderColor(Color::Srgba(DARK_SEA_GREEN)),
+                        background_color: BackgroundColor(Color::Srgba(LAVENDER)),
+                        ..default()
+                    },
+                    MenuOptions::Controls,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(TextBundle::from_section(
+                        "Button",
+                        TextStyle {
+                            // font: server.load("fonts/TitilliumWeb-SemiBold.ttf"),
+                            font_size,
+                            color: Srgba::rgb(0.1, 0.1, 0.1).into(),
+                            ..default()
+                        },
+                    ));
+                });
             parent
                 .spawn((
                     ButtonBundle {
@@ -180,6 +275,8 @@ fn game_menu(
             MenuOptions::Resume => text.sections[0].value = "Resume Game".into(),
             MenuOptions::Exit => text.sections[0].value = "Quit Game".into(),
             MenuOptions::FreeMode => text.sections[0].value = "Freeform".into(),
+            MenuOptions::Calibrate => text.sections[0].value = "Calibrate".into(),
+            MenuOptions::Controls => text.sections[0].value = "Controls".into(),
         }
     }
 }
@@ -195,6 +292,7 @@ fn interact_game_menu(
         ),
         (Changed<Interaction>, With<Button>),
     >,
+    track: Res<Track>,
     mut next_app_state: ResMut<NextState<ApplicationState>>,
     mut next_mode_state: ResMut<NextState<ModeState>>,
 ) {
@@ -207,7 +305,10 @@ fn interact_game_menu(
                     next_app_state.set(ApplicationState::Loading);
                     next_mode_state.set(ModeState::Singleplayer);
                 }
-                MenuOptions::Resume => next_app_state.set(ApplicationState::InGame),
+                MenuOptions::Resume if track.is_loaded() => {
+                    next_app_state.set(ApplicationState::InGame)
+                }
+                MenuOptions::Resume => {}
                 MenuOptions::Exit => {
                     next_app_state.set(ApplicationState::Exit);
                     next_mode_state.set(ModeState::NotInGame);
@@ -216,6 +317,8 @@ fn interact_game_menu(
                     next_app_state.set(ApplicationState::Loading);
                     next_mode_state.set(ModeState::Freeform);
                 }
+                MenuOptions::Calibrate => next_app_state.set(ApplicationState::Calibration),
+                MenuOptions::Controls => next_app_state.set(ApplicationState::Controls),
             },
             Interaction::Hovered => {
                 *color = BackgroundColor(Color::Srgba(DARK_SEA_GREEN));
@@ -229,28 +332,456 @@ fn interact_game_menu(
     }
 }
 
-fn clear_menu(mut commands: Commands, mut query: Query<Entity, With<Node>>) {
-    for entity in query.iter_mut() {
-        commands.entity(entity).despawn();
+#[derive(Component, Clone, Copy)]
+enum EndOptions {
+    Retry,
+    Menu,
+}
+
+fn end_screen_root() -> NodeBundle {
+    NodeBundle {
+        style: Style {
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            align_items: AlignItems::Center,
+            justify_content: JustifyContent::Center,
+            row_gap: Val::Px(16.0),
+            ..default()
+        },
+        ..default()
     }
 }
 
-fn pause_screen(mut commands: Commands, _server: Res<AssetServer>) {
-    commands.spawn(Text2dBundle {
-        text: Text::from_section(
-            "PAUSED",
-            TextStyle {
-                font_size: 32.,
-                ..default()
+fn spawn_end_buttons(parent: &mut ChildBuilder) {
+    let font_size = 24.0;
+
+    for (option, label) in [(EndOptions::Retry, "Retry"), (EndOptions::Menu, "Menu")] {
+        parent
+            .spawn((
+                ButtonBundle {
+                    style: Style {
+                        width: Val::Px(150.0),
+                        height: Val::Px(65.0),
+                        border: UiRect::all(Val::Px(5.0)),
+                        justify_content: JustifyContent::Center,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    border_color: BorderColor(Color::Srgba(DARK_SEA_GREEN)),
+                    background_color: BackgroundColor(Color::Srgba(LAVENDER)),
+                    ..default()
+                },
+                option,
+            ))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    label,
+                    TextStyle {
+                        font_size,
+                        color: Srgba::rgb(0.1, 0.1, 0.1).into(),
+                        ..default()
+                    },
+                ));
+            });
+    }
+}
+
+fn fail_setup(mut commands: Commands) {
+    commands
+        .spawn((end_screen_root(), MenuLayer::Failed))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "SONG FAILED",
+                TextStyle {
+                    font_size: 32.,
+                    ..default()
+                },
+            ));
+            spawn_end_buttons(parent);
+        });
+}
+
+fn results_setup(mut commands: Commands, score: Res<Score>, stats: Res<JudgmentStats>) {
+    let lines = [
+        format!("GRADE: {}", stats.grade()),
+        format!("SCORE: {}", score.value),
+        format!("MAX COMBO: {}", score.max_combo),
+        format!("ACCURACY: {:.1}%", stats.accuracy() * 100.),
+        format!(
+            "PERFECT {}  GREAT {}  GOOD {}  MISS {}",
+            stats.perfect, stats.great, stats.good, stats.miss
+        ),
+    ];
+
+    commands
+        .spawn((end_screen_root(), MenuLayer::Results))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "RESULTS",
+                TextStyle {
+                    font_size: 32.,
+                    ..default()
+                },
+            ));
+            for line in lines {
+                parent.spawn(TextBundle::from_section(
+                    line,
+                    TextStyle {
+                        font_size: 20.,
+                        ..default()
+                    },
+                ));
+            }
+            spawn_end_buttons(parent);
+        });
+}
+
+fn interact_end_menu(
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &EndOptions,
+            &mut BackgroundColor,
+            &mut BorderColor,
+        ),
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut next_app_state: ResMut<NextState<ApplicationState>>,
+    mut next_mode_state: ResMut<NextState<ModeState>>,
+) {
+    for (interaction, end_options, mut color, mut border) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => match end_options {
+                EndOptions::Retry => next_app_state.set(ApplicationState::Loading),
+                EndOptions::Menu => {
+                    next_app_state.set(ApplicationState::Menu);
+                    next_mode_state.set(ModeState::NotInGame);
+                }
             },
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::Srgba(DARK_SEA_GREEN));
+                *border = BorderColor(Color::Srgba(BLACK));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::Srgba(LAVENDER));
+                *border = BorderColor(Color::Srgba(DARK_SEA_GREEN));
+            }
+        }
+    }
+}
+
+/// Action waiting for the player to press its new key.
+#[derive(Resource, Default)]
+pub(crate) struct Rebinding(pub(crate) Option<Action>);
+
+#[derive(Component)]
+struct RebindButton(Action);
+
+fn controls_setup(mut commands: Commands, mut rebinding: ResMut<Rebinding>) {
+    rebinding.0 = None;
+    let font_size = 20.0;
+
+    commands
+        .spawn((end_screen_root(), MenuLayer::Controls))
+        .with_children(|parent| {
+            parent.spawn(TextBundle::from_section(
+                "CONTROLS\nCLICK AN ACTION, THEN PRESS ITS NEW KEY OR MIDI CONTROL",
+                TextStyle {
+                    font_size: 28.0,
+                    ..default()
+                },
+            ));
+            // two columns, so every action fits on screen
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        height: Val::Px(440.0),
+                        flex_direction: FlexDirection::Column,
+                        flex_wrap: FlexWrap::Wrap,
+                        align_content: AlignContent::Center,
+                        row_gap: Val::Px(8.0),
+                        column_gap: Val::Px(16.0),
+                        ..default()
+                    },
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for action in Action::REBINDABLE {
+                        parent
+                            .spawn((
+                                ButtonBundle {
+                                    style: Style {
+                                        width: Val::Px(480.0),
+                                        height: Val::Px(36.0),
+                                        border: UiRect::all(Val::Px(3.0)),
+                                        justify_content: JustifyContent::Center,
+                                        align_items: AlignItems::Center,
+                                        ..default()
+                                    },
+                                    border_color: BorderColor(Color::Srgba(DARK_SEA_GREEN)),
+                                    background_color: BackgroundColor(Color::Srgba(LAVENDER)),
+                                    ..default()
+                                },
+                                RebindButton(action),
+                            ))
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section(
+                                    "",
+                                    TextStyle {
+                                        font_size,
+                                        color: Srgba::rgb(0.1, 0.1, 0.1).into(),
+                                        ..default()
+                                    },
+                                ));
+                            });
+                    }
+                });
+        });
+}
+
+fn interact_controls(
+    mut interaction_query: Query<
+        (
+            &Interaction,
+            &RebindButton,
+            &mut BackgroundColor,
+            &mut BorderColor,
         ),
-        transform: Transform::from_translation(Vec3::new(4., 0., 104.)),
-        ..default()
+        (Changed<Interaction>, With<Button>),
+    >,
+    mut rebinding: ResMut<Rebinding>,
+) {
+    for (interaction, button, mut color, mut border) in interaction_query.iter_mut() {
+        match *interaction {
+            Interaction::Pressed => rebinding.0 = Some(button.0),
+            Interaction::Hovered => {
+                *color = BackgroundColor(Color::Srgba(DARK_SEA_GREEN));
+                *border = BorderColor(Color::Srgba(BLACK));
+            }
+            Interaction::None => {
+                *color = BackgroundColor(Color::Srgba(LAVENDER));
+                *border = BorderColor(Color::Srgba(DARK_SEA_GREEN));
+            }
+        }
+    }
+}
+
+fn capture_rebind(
+    keys: Res<ButtonInput<KeyCode>>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    let Some(action) = rebinding.0 else {
+        return;
+    };
+    // the press that picked the action isn't the one to bind
+    if rebinding.is_changed() {
+        return;
+    }
+    // escape and the pad's menu button stay on the menu so there's always a way out
+    if let Some(key) = keys.get_just_pressed().find(|key| **key != KeyCode::Escape) {
+        settings.bindings.bind(action, *key);
+    } else if let Some(button) = pad_buttons
+        .get_just_pressed()
+        .find(|button| settings.pad_bindings.button(Action::Menu) != Some(button.button_type))
+    {
+        settings.pad_bindings.bind(action, button.button_type);
+    } else {
+        return;
+    }
+    settings.save();
+    rebinding.0 = None;
+}
+
+fn controls_display(
+    settings: Res<Settings>,
+    gamepads: Res<Gamepads>,
+    rebinding: Res<Rebinding>,
+    query: Query<(&RebindButton, &Children)>,
+    mut text_query: Query<&mut Text>,
+) {
+    for (button, children) in query.iter() {
+        let Ok(mut text) = text_query.get_mut(children[0]) else {
+            continue;
+        };
+        let key = match settings.bindings.key(button.0) {
+            _ if rebinding.0 == Some(button.0) => "...".to_string(),
+            Some(key) => format!("{:?}", key),
+            None => "-".to_string(),
+        };
+        let mut label = format!("{}: {}", button.0.label(), key);
+        if let Some(pad) = settings.pad_bindings.button(button.0) {
+            if gamepads.iter().next().is_some() {
+                label.push_str(&format!(" / {:?}", pad));
+            }
+        }
+        match settings.midi_bindings.control(button.0) {
+            Some(MidiControl::Note(note)) => label.push_str(&format!(" / NOTE {}", note)),
+            Some(MidiControl::Cc(controller)) => label.push_str(&format!(" / CC {}", controller)),
+            None => {}
+        }
+        text.sections[0].value = label;
+    }
+}
+
+/// Button a gamepad has moved onto, counted in reading order.
+#[derive(Resource, Default)]
+struct PadFocus(Option<usize>);
+
+/// Lets a gamepad step through whichever buttons are on screen and press them.
+fn gamepad_menu_nav(
+    gamepads: Res<Gamepads>,
+    pad_buttons: Res<ButtonInput<GamepadButton>>,
+    app_state: Res<State<ApplicationState>>,
+    rebinding: Res<Rebinding>,
+    mut focus: ResMut<PadFocus>,
+    mut query: Query<
+        (
+            &GlobalTransform,
+            &mut Interaction,
+            &mut BackgroundColor,
+            &mut BorderColor,
+        ),
+        With<Button>,
+    >,
+) {
+    if app_state.is_changed() {
+        focus.0 = None;
+    }
+    // a button pressed while the controls screen waits for one is being bound
+    if rebinding.0.is_some() {
+        return;
+    }
+    let pressed = |button_type| {
+        gamepads
+            .iter()
+            .any(|gamepad| pad_buttons.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+    let step: isize =
+        if pressed(GamepadButtonType::DPadRight) || pressed(GamepadButtonType::DPadDown) {
+            1
+        } else if pressed(GamepadButtonType::DPadLeft) || pressed(GamepadButtonType::DPadUp) {
+            -1
+        } else {
+            0
+        };
+
+    let mut buttons: Vec<_> = query.iter_mut().collect();
+    if buttons.is_empty() {
+        focus.0 = None;
+        return;
+    }
+    buttons.sort_by(|(a, ..), (b, ..)| {
+        let (a, b) = (a.translation(), b.translation());
+        a.y.total_cmp(&b.y).then(a.x.total_cmp(&b.x))
     });
+    let count = buttons.len() as isize;
+    if step != 0 {
+        if let Some((_, _, color, border)) = focus.0.and_then(|index| buttons.get_mut(index)) {
+            **color = BackgroundColor(Color::Srgba(LAVENDER));
+            **border = BorderColor(Color::Srgba(DARK_SEA_GREEN));
+        }
+        let next = match focus.0 {
+            Some(index) => (index as isize + step).rem_euclid(count),
+            None => 0,
+        };
+        focus.0 = Some(next as usize);
+    }
+
+    let Some((_, interaction, color, border)) = focus.0.and_then(|index| buttons.get_mut(index))
+    else {
+        return;
+    };
+    **color = BackgroundColor(Color::Srgba(DARK_SEA_GREEN));
+    **border = BorderColor(Color::Srgba(BLACK));
+    if pressed(GamepadButtonType::South) {
+        **interaction = Interaction::Pressed;
+    }
 }
 
-fn clear_pause(mut commands: Commands, mut query: Query<Entity, With<Text>>) {
+fn clear_menu(mut commands: Commands, mut query: Query<Entity, With<Node>>) {
     for entity in query.iter_mut() {
         commands.entity(entity).despawn();
     }
 }
+
+#[derive(Component)]
+struct PauseDispTag;
+
+fn pause_screen(mut commands: Commands, _server: Res<AssetServer>) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "PAUSED",
+                TextStyle {
+                    font_size: 32.,
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(4., 0., 104.)),
+            ..default()
+        },
+        PauseDispTag,
+    ));
+}
+
+fn clear_pause(mut commands: Commands, query: Query<Entity, With<PauseDispTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Beats counted before play resumes.
+const COUNT_IN_BEATS: u32 = 3;
+
+#[derive(Component)]
+struct CountIn {
+    timer: Timer,
+}
+
+fn count_in_setup(mut commands: Commands, track: Res<Track>, clock: Res<TrackClock>) {
+    // counted at the tempo where the track paused, so the last beat leads into the next
+    let beat_secs = track
+        .sequencer
+        .tempo_map()
+        .beat_secs_at(clock.elapsed_secs()) as f32;
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "",
+                TextStyle {
+                    font_size: 48.,
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(Vec3::new(4., 0., 104.)),
+            ..default()
+        },
+        CountIn {
+            timer: Timer::from_seconds(beat_secs * COUNT_IN_BEATS as f32, TimerMode::Once),
+        },
+        PauseDispTag,
+    ));
+}
+
+fn count_in(
+    time: Res<Time>,
+    mut query: Query<(&mut CountIn, &mut Text)>,
+    mut next_pause_state: ResMut<NextState<PauseState>>,
+) {
+    for (mut count_in, mut text) in query.iter_mut() {
+        if count_in.timer.tick(time.delta()).finished() {
+            next_pause_state.set(PauseState::Unpaused);
+            continue;
+        }
+        let remaining = 1. - count_in.timer.fraction();
+        let count = (remaining * COUNT_IN_BEATS as f32).ceil() as u32;
+        text.sections[0].value = count.to_string();
+    }
+}
+
+fn unpause(mut next_pause_state: ResMut<NextState<PauseState>>) {
+    next_pause_state.set(PauseState::Unpaused);
+}
@@ -0,0 +1,461 @@
+use std::{collections::VecDeque, time::Duration};
+
+use bevy::{prelude::*, time::Stopwatch, utils::Instant};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    action::{clear_actions, Action, ActionSet, ActionTimes},
+    menu::Rebinding,
+    osc::OscType,
+    pot::PotType,
+    settings::Settings,
+    ApplicationState,
+};
+
+/// Controller value at or above which a CC counts as held.
+const CC_ON: u8 = 64;
+
+pub(super) struct MidiPlugin;
+
+impl Plugin for MidiPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(not(target_arch = "wasm32"))]
+        app.add_systems(Startup, load_midi_script);
+        #[cfg(feature = "midi")]
+        app.add_systems(Startup, port::connect_midi)
+            .add_systems(PreUpdate, port::read_midi_port.before(midi_actions));
+        app.add_systems(
+            PreUpdate,
+            (play_midi_script, midi_actions)
+                .chain()
+                .in_set(ActionSet)
+                .after(clear_actions),
+        );
+        app.add_systems(
+            Update,
+            learn_midi.run_if(in_state(ApplicationState::Controls)),
+        );
+        app.add_event::<MidiEvent>();
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum MidiMessage {
+    NoteOn { note: u8, velocity: u8 },
+    NoteOff { note: u8 },
+    ControlChange { controller: u8, value: u8 },
+}
+
+impl MidiMessage {
+    /// Decode a raw channel message, ignoring the channel; anything else is `None`.
+    pub(crate) fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        match (status & 0xf0, data) {
+            (0x90, &[note, velocity, ..]) if velocity > 0 => {
+                Some(MidiMessage::NoteOn { note, velocity })
+            }
+            (0x80 | 0x90, &[note, _, ..]) => Some(MidiMessage::NoteOff { note }),
+            (0xb0, &[controller, value, ..]) => {
+                Some(MidiMessage::ControlChange { controller, value })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A MIDI message from a port or a script, in the order it arrived.
+#[derive(Event, Clone, Copy, Debug)]
+pub(crate) struct MidiEvent {
+    pub(crate) message: MidiMessage,
+    /// When the message came in, or was due for a scripted one, rather than when it was
+    /// read.
+    pub(crate) at: Instant,
+}
+
+/// Note or controller that an action listens to, on any channel.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum MidiControl {
+    Note(u8),
+    Cc(u8),
+}
+
+impl MidiControl {
+    /// The control a message touches, and whether it is now held.
+    fn from_message(message: MidiMessage) -> (Self, bool) {
+        match message {
+            MidiMessage::NoteOn { note, .. } => (MidiControl::Note(note), true),
+            MidiMessage::NoteOff { note } => (MidiControl::Note(note), false),
+            MidiMessage::ControlChange { controller, value } => {
+                (MidiControl::Cc(controller), value >= CC_ON)
+            }
+        }
+    }
+}
+
+/// MIDI control each action is bound to.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct MidiBindings(Vec<(Action, MidiControl)>);
+
+impl Default for MidiBindings {
+    fn default() -> Self {
+        // oscillators on the octave below middle C, pots from middle C up the white keys
+        MidiBindings(vec![
+            (Action::Osc(OscType::Sine), MidiControl::Note(48)),
+            (Action::Osc(OscType::Triangle), MidiControl::Note(50)),
+            (Action::Osc(OscType::Square), MidiControl::Note(52)),
+            (Action::Osc(OscType::Sawtooth), MidiControl::Note(53)),
+            (Action::Pot(PotType::PotJ), MidiControl::Note(60)),
+            (Action::Pot(PotType::PotI), MidiControl::Note(62)),
+            (Action::Pot(PotType::PotK), MidiControl::Note(64)),
+            (Action::Pot(PotType::PotO), MidiControl::Note(65)),
+            (Action::Pot(PotType::PotL), MidiControl::Note(67)),
+        ])
+    }
+}
+
+impl MidiBindings {
+    pub(crate) fn control(&self, action: Action) -> Option<MidiControl> {
+        self.0
+            .iter()
+            .find(|(bound, _)| *bound == action)
+            .map(|(_, control)| *control)
+    }
+
+    /// Bind `control` to `action`, taking it off any action that had it.
+    pub(crate) fn bind(&mut self, action: Action, control: MidiControl) {
+        self.0
+            .retain(|(bound, bound_control)| *bound != action && *bound_control != control);
+        self.0.push((action, control));
+    }
+}
+
+fn midi_actions(
+    mut ev_midi: EventReader<MidiEvent>,
+    settings: Res<Settings>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut times: ResMut<ActionTimes>,
+) {
+    for ev in ev_midi.read() {
+        let (control, held) = MidiControl::from_message(ev.message);
+        for (action, bound) in settings.midi_bindings.0.iter() {
+            if *bound != control {
+                continue;
+            }
+            if held {
+                times.press(&mut actions, *action, ev.at);
+            } else {
+                actions.release(*action);
+            }
+        }
+    }
+}
+
+/// Binds the next note or controller to the action waiting on the controls screen.
+fn learn_midi(
+    mut ev_midi: EventReader<MidiEvent>,
+    mut rebinding: ResMut<Rebinding>,
+    mut settings: ResMut<Settings>,
+) {
+    for ev in ev_midi.read() {
+        let Some(action) = rebinding.0 else {
+            continue;
+        };
+        let (control, held) = MidiControl::from_message(ev.message);
+        if held {
+            settings.midi_bindings.bind(action, control);
+            settings.save();
+            rebinding.0 = None;
+        }
+    }
+}
+
+/// Plays back MIDI messages at set times, standing in for a controller.
+#[derive(Resource, Default)]
+pub(crate) struct MidiScript {
+    clock: Stopwatch,
+    messages: VecDeque<(Duration, MidiMessage)>,
+}
+
+impl MidiScript {
+    /// `messages` are seconds after the script is inserted, in order.
+    pub(crate) fn new(messages: impl IntoIterator<Item = (f64, MidiMessage)>) -> Self {
+        MidiScript {
+            clock: Stopwatch::new(),
+            messages: messages
+                .into_iter()
+                .map(|(secs, message)| (Duration::from_secs_f64(secs), message))
+                .collect(),
+        }
+    }
+
+    /// Reads a JSON list of `[secs, [status, data...]]` pairs, skipping messages that
+    /// aren't notes or controllers.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_json(text: &str) -> Result<Self, String> {
+        let messages: Vec<(f64, Vec<u8>)> =
+            serde_json::from_str(text).map_err(|err| err.to_string())?;
+        Ok(MidiScript::new(messages.into_iter().filter_map(
+            |(secs, bytes)| MidiMessage::parse(&bytes).map(|message| (secs, message)),
+        )))
+    }
+}
+
+/// Environment variable naming a JSON file of `[secs, [status, data...]]` pairs to play
+/// in place of a controller.
+#[cfg(not(target_arch = "wasm32"))]
+const MIDI_SCRIPT_VAR: &str = "OPTICAL_RACE_MIDI_SCRIPT";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn load_midi_script(mut commands: Commands) {
+    let Ok(path) = std::env::var(MIDI_SCRIPT_VAR) else {
+        return;
+    };
+    let script = std::fs::read_to_string(&path)
+        .map_err(|err| err.to_string())
+        .and_then(|text| MidiScript::from_json(&text));
+    match script {
+        Ok(script) => {
+            info!("playing MIDI script {}", path);
+            commands.insert_resource(script);
+        }
+        Err(err) => error!("could not load MIDI script {}: {}", path, err),
+    }
+}
+
+fn play_midi_script(
+    real: Res<Time<Real>>,
+    script: Option<ResMut<MidiScript>>,
+    mut ev_midi: EventWriter<MidiEvent>,
+) {
+    let Some(mut script) = script else {
+        return;
+    };
+    script.clock.tick(real.delta());
+    let frame_start = real.last_update().unwrap_or_else(Instant::now);
+    while let Some(&(at, message)) = script.messages.front() {
+        let elapsed = script.clock.elapsed();
+        if at > elapsed {
+            break;
+        }
+        // the clock is as of the start of the frame, so the message was due that long
+        // before it
+        let due = frame_start.checked_sub(elapsed - at).unwrap_or(frame_start);
+        ev_midi.send(MidiEvent { message, at: due });
+        script.messages.pop_front();
+    }
+}
+
+/// Hardware input through the first MIDI port found at startup.
+#[cfg(feature = "midi")]
+mod port {
+    use std::{
+        sync::{
+            mpsc::{channel, Receiver},
+            Mutex,
+        },
+        time::Duration,
+    };
+
+    use bevy::{prelude::*, utils::Instant};
+    use midir::{Ignore, MidiInput, MidiInputConnection};
+
+    use super::{MidiEvent, MidiMessage};
+
+    #[derive(Resource)]
+    pub(super) struct MidiPort(Mutex<Receiver<(MidiMessage, Instant)>>);
+
+    pub(super) fn connect_midi(world: &mut World) {
+        let mut midi_in = match MidiInput::new("optical-race") {
+            Ok(midi_in) => midi_in,
+            Err(err) => {
+                warn!("MIDI unavailable: {}", err);
+                return;
+            }
+        };
+        midi_in.ignore(Ignore::All);
+        let Some(port) = midi_in.ports().into_iter().next() else {
+            info!("no MIDI input ports found");
+            return;
+        };
+        let name = midi_in.port_name(&port).unwrap_or_default();
+
+        let (sender, receiver) = channel();
+        // midir stamps are microseconds from an arbitrary start, so they are pinned to
+        // when the first message arrived
+        let mut anchor: Option<(u64, Instant)> = None;
+        let connection = midi_in.connect(
+            &port,
+            "optical-race-input",
+            move |stamp, bytes, _| {
+                let now = Instant::now();
+                let &mut (first, first_at) = anchor.get_or_insert((stamp, now));
+                let at = first_at + Duration::from_micros(stamp.saturating_sub(first));
+                if let Some(message) = MidiMessage::parse(bytes) {
+                    let _ = sender.send((message, at.min(now)));
+                }
+            },
+            (),
+        );
+        match connection {
+            Ok(connection) => {
+                info!("listening to MIDI port {}", name);
+                // the connection closes when dropped, so the world keeps it
+                world.insert_non_send_resource::<MidiInputConnection<()>>(connection);
+                world.insert_resource(MidiPort(Mutex::new(receiver)));
+            }
+            Err(err) => warn!("could not open MIDI port {}: {}", name, err),
+        }
+    }
+
+    pub(super) fn read_midi_port(port: Option<Res<MidiPort>>, mut ev_midi: EventWriter<MidiEvent>) {
+        let Some(port) = port else {
+            return;
+        };
+        let Ok(receiver) = port.0.lock() else {
+            return;
+        };
+        for (message, at) in receiver.try_iter() {
+            ev_midi.send(MidiEvent { message, at });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::time::TimeUpdateStrategy;
+
+    use super::*;
+
+    #[test]
+    fn note_on_without_velocity_is_a_note_off() {
+        assert_eq!(
+            MidiMessage::parse(&[0x93, 60, 0]),
+            Some(MidiMessage::NoteOff { note: 60 })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x93, 60, 100]),
+            Some(MidiMessage::NoteOn {
+                note: 60,
+                velocity: 100
+            })
+        );
+        assert_eq!(
+            MidiMessage::parse(&[0x80, 60, 64]),
+            Some(MidiMessage::NoteOff { note: 60 })
+        );
+    }
+
+    #[test]
+    fn running_status_and_short_messages_are_dropped() {
+        // data bytes carrying on from an earlier status byte
+        assert_eq!(MidiMessage::parse(&[60, 100]), None);
+        assert_eq!(MidiMessage::parse(&[0x90, 60]), None);
+        assert_eq!(MidiMessage::parse(&[0x80, 60]), None);
+        assert_eq!(MidiMessage::parse(&[0xb0, 7]), None);
+        assert_eq!(MidiMessage::parse(&[]), None);
+    }
+
+    #[test]
+    fn learning_a_control_replaces_the_old_binding() {
+        let mut bindings = MidiBindings::default();
+        let sine = Action::Osc(OscType::Sine);
+        let pot = Action::Pot(PotType::PotJ);
+
+        bindings.bind(sine, MidiControl::Cc(1));
+        assert_eq!(bindings.control(sine), Some(MidiControl::Cc(1)));
+
+        // the pot takes the controller from the oscillator
+        bindings.bind(pot, MidiControl::Cc(1));
+        assert_eq!(bindings.control(pot), Some(MidiControl::Cc(1)));
+        assert_eq!(bindings.control(sine), None);
+        assert_eq!(
+            bindings
+                .0
+                .iter()
+                .filter(|(action, _)| *action == pot)
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn scripted_messages_press_and_release_actions() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            10,
+        )));
+        app.init_resource::<ButtonInput<Action>>()
+            .init_resource::<ActionTimes>()
+            .init_resource::<Settings>()
+            .add_event::<MidiEvent>()
+            .add_systems(Update, (play_midi_script, midi_actions).chain());
+        // a clock message in the middle is skipped
+        let script =
+            MidiScript::from_json("[[0.02, [144, 60, 90]], [0.03, [248]], [0.05, [128, 60, 0]]]")
+                .unwrap();
+        assert_eq!(script.messages.len(), 2);
+        app.insert_resource(script);
+
+        let pot = Action::Pot(PotType::PotJ);
+        let pressed = |app: &App| app.world().resource::<ButtonInput<Action>>().pressed(pot);
+        app.update();
+        assert!(!pressed(&app));
+        assert_eq!(app.world().resource::<ActionTimes>().pressed_at(pot), None);
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(pressed(&app));
+        assert!(app
+            .world()
+            .resource::<ActionTimes>()
+            .pressed_at(pot)
+            .is_some());
+
+        for _ in 0..3 {
+            app.update();
+        }
+        assert!(!pressed(&app));
+    }
+
+    #[test]
+    fn scripted_presses_are_stamped_when_they_were_due() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            10,
+        )));
+        app.init_resource::<ButtonInput<Action>>()
+            .init_resource::<ActionTimes>()
+            .init_resource::<Settings>()
+            .add_event::<MidiEvent>()
+            .add_systems(Update, (play_midi_script, midi_actions).chain());
+        let due = Duration::from_millis(25);
+        let pot = Action::Pot(PotType::PotJ);
+        app.insert_resource(MidiScript::new([(
+            due.as_secs_f64(),
+            MidiMessage::NoteOn {
+                note: 60,
+                velocity: 90,
+            },
+        )]));
+
+        while app
+            .world()
+            .resource::<ActionTimes>()
+            .pressed_at(pot)
+            .is_none()
+        {
+            app.update();
+        }
+        let world = app.world();
+        let frame_start = world.resource::<Time<Real>>().last_update().unwrap();
+        let late = world.resource::<MidiScript>().clock.elapsed() - due;
+        assert!(late > Duration::ZERO);
+        assert_eq!(
+            world.resource::<ActionTimes>().pressed_at(pot),
+            Some(frame_start - late)
+        );
+    }
+}
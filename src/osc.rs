@@ -1,6 +1,11 @@
 use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::{ApplicationState, ModeState};
+use crate::{
+    action::{Action, ActionSet},
+    pointer::Touchable,
+    ApplicationState, ModeState,
+};
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
 pub(super) struct OscSet;
@@ -9,8 +14,11 @@ pub(super) struct OscPlugin;
 
 impl Plugin for OscPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(ApplicationState::Loading), load_oscs.in_set(OscSet));
-        app.add_systems(Update, osc_inputs.in_set(OscSet));
+        app.add_systems(
+            OnEnter(ApplicationState::Loading),
+            (unload_oscs, load_oscs).chain().in_set(OscSet),
+        );
+        app.add_systems(PreUpdate, osc_inputs.in_set(OscSet).after(ActionSet));
         app.add_systems(OnEnter(ModeState::NotInGame), unload_oscs.in_set(OscSet));
     }
 }
@@ -25,138 +33,99 @@ pub(crate) fn fetch_osc_tex(osc_type: OscType) -> String {
     .into()
 }
 
-fn load_oscs(
-    mut commands: Commands,
-    server: Res<AssetServer>,
-    mut next_app_state: ResMut<NextState<ApplicationState>>,
-    mut next_game_state: ResMut<NextState<ModeState>>,
-) {
+fn load_oscs(mut commands: Commands, server: Res<AssetServer>) {
     let origin_x = -128.;
     let origin_y = 0.;
 
     let sine_sprite: Handle<Image> = server.load("sine_tile.png");
+    let sine_pos = Vec3::new(origin_x - 32., origin_y, 0.);
     let sine = OscBundle {
         tag: OscTag,
         osc_type: OscType::Sine,
         state: OscState::Inactive,
+        touch: Touchable::tile(Action::Osc(OscType::Sine), sine_pos),
         sprite: SpriteBundle {
             texture: sine_sprite,
-            transform: Transform::from_translation(Vec3::new(origin_x - 32., origin_y, 0.)),
+            transform: Transform::from_translation(sine_pos),
             ..default()
         },
     };
     commands.spawn(sine);
 
     let triangle_sprite: Handle<Image> = server.load("triangle_tile.png");
+    let triangle_pos = Vec3::new(origin_x, origin_y + 32., 0.);
     let triangle = OscBundle {
         tag: OscTag,
         osc_type: OscType::Triangle,
         state: OscState::Inactive,
+        touch: Touchable::tile(Action::Osc(OscType::Triangle), triangle_pos),
         sprite: SpriteBundle {
             texture: triangle_sprite,
-            transform: Transform::from_translation(Vec3::new(origin_x, origin_y + 32., 0.)),
+            transform: Transform::from_translation(triangle_pos),
             ..default()
         },
     };
     commands.spawn(triangle);
 
     let square_sprite: Handle<Image> = server.load("square_tile.png");
+    let square_pos = Vec3::new(origin_x, origin_y, 0.);
     let square = OscBundle {
         tag: OscTag,
         osc_type: OscType::Square,
         state: OscState::Inactive,
+        touch: Touchable::tile(Action::Osc(OscType::Square), square_pos),
         sprite: SpriteBundle {
             texture: square_sprite,
-            transform: Transform::from_translation(Vec3::new(origin_x, origin_y, 0.)),
+            transform: Transform::from_translation(square_pos),
             ..default()
         },
     };
     commands.spawn(square);
 
     let sawtooth_sprite: Handle<Image> = server.load("saw_tile.png");
+    let sawtooth_pos = Vec3::new(origin_x + 32., origin_y, 0.);
     let sawtooth = OscBundle {
         tag: OscTag,
         osc_type: OscType::Sawtooth,
         state: OscState::Inactive,
+        touch: Touchable::tile(Action::Osc(OscType::Sawtooth), sawtooth_pos),
         sprite: SpriteBundle {
             texture: sawtooth_sprite,
-            transform: Transform::from_translation(Vec3::new(origin_x + 32., origin_y, 0.)),
+            transform: Transform::from_translation(sawtooth_pos),
             ..default()
         },
     };
     commands.spawn(sawtooth);
-
-    next_app_state.set(ApplicationState::InGame);
-    next_game_state.set(ModeState::Singleplayer);
 }
 
 fn osc_inputs(
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ButtonInput<Action>>,
     mut query: Query<(Entity, &OscType, &mut OscState, &mut Handle<Image>), With<OscTag>>,
     server: Res<AssetServer>,
 ) {
     for (_entity, osc_type, mut state, mut texture) in query.iter_mut() {
-        for key in keys.get_pressed() {
-            match key {
-                KeyCode::KeyD => {
-                    if osc_type == &OscType::Sawtooth {
-                        *state = OscState::Active;
-                        *texture = server.load("saw_tile_on.png");
-                    }
-                }
-                KeyCode::KeyA => {
-                    if osc_type == &OscType::Sine {
-                        *state = OscState::Active;
-                        *texture = server.load("sine_tile_on.png");
-                    }
-                }
-                KeyCode::KeyS => {
-                    if osc_type == &OscType::Square {
-                        *state = OscState::Active;
-                        *texture = server.load("square_tile_on.png");
-                    }
-                }
-                KeyCode::KeyW => {
-                    if osc_type == &OscType::Triangle {
-                        *state = OscState::Active;
-                        *texture = server.load("triangle_tile_on.png");
-                    }
-                }
-                _ => {}
-            }
+        let action = Action::Osc(*osc_type);
+        if actions.pressed(action) {
+            *state = OscState::Active;
+            *texture = server.load(fetch_osc_on_tex(*osc_type));
         }
-        for key in keys.get_just_released() {
-            match key {
-                KeyCode::KeyD => {
-                    if osc_type == &OscType::Sawtooth {
-                        *state = OscState::Inactive;
-                        *texture = server.load("saw_tile.png");
-                    }
-                }
-                KeyCode::KeyA => {
-                    if osc_type == &OscType::Sine {
-                        *state = OscState::Inactive;
-                        *texture = server.load("sine_tile.png");
-                    }
-                }
-                KeyCode::KeyS => {
-                    if osc_type == &OscType::Square {
-                        *state = OscState::Inactive;
-                        *texture = server.load("square_tile.png");
-                    }
-                }
-                KeyCode::KeyW => {
-                    if osc_type == &OscType::Triangle {
-                        *state = OscState::Inactive;
-                        *texture = server.load("triangle_tile.png");
-                    }
-                }
-                _ => {}
-            }
+        if actions.just_released(action) {
+            *state = OscState::Inactive;
+            *texture = server.load(fetch_osc_tex(*osc_type));
         }
     }
 }
 
+fn fetch_osc_on_tex(osc_type: OscType) -> String {
+    match osc_type {
+        OscType::Sine => "sine_tile_on.png",
+        OscType::Triangle => "triangle_tile_on.png",
+        OscType::Square => "square_tile_on.png",
+        OscType::Sawtooth => "saw_tile_on.png",
+    }
+    .into()
+}
+
 fn unload_oscs(mut commands: Commands, query: Query<Entity, With<OscTag>>) {
     for entity in query.iter() {
         commands.entity(entity).despawn();
@@ -169,7 +138,7 @@ pub(crate) enum OscState {
     Inactive,
 }
 
-#[derive(Component, PartialEq, Clone, Copy, Debug)]
+#[derive(Component, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) enum OscType {
     Sine,
     Triangle,
@@ -185,5 +154,6 @@ struct OscBundle {
     tag: OscTag,
     osc_type: OscType,
     state: OscState,
+    touch: Touchable,
     sprite: SpriteBundle,
 }
@@ -0,0 +1,225 @@
+use bevy::{
+    input::touch::Touches,
+    prelude::*,
+    utils::{HashMap, Instant},
+    window::PrimaryWindow,
+};
+
+use crate::{
+    action::{clear_actions, Action, ActionSet, ActionTimes},
+    ApplicationState, ModeState,
+};
+
+/// Half the width of an oscillator or pot tile, before any layout scale.
+const TILE_HALF: f32 = 16.;
+/// How much bigger the controls are drawn once the player is using a touch screen.
+const TOUCH_SCALE: f32 = 2.;
+/// Where the scaled-up oscillators and pots move to, so each sits under a thumb.
+const TOUCH_OSC_SHIFT: Vec2 = Vec2::new(0., -160.);
+const TOUCH_POT_SHIFT: Vec2 = Vec2::new(128., -192.);
+const PAUSE_TAB_POS: Vec3 = Vec3::new(480., 300., 104.);
+
+pub(super) struct PointerPlugin;
+
+impl Plugin for PointerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            PreUpdate,
+            (detect_touch, pointer_actions)
+                .chain()
+                .in_set(ActionSet)
+                .after(clear_actions),
+        );
+        app.add_systems(
+            OnEnter(ApplicationState::Loading),
+            (unload_pause_tab, load_pause_tab).chain(),
+        );
+        app.add_systems(OnEnter(ModeState::NotInGame), unload_pause_tab);
+        app.add_systems(Update, touch_layout);
+        app.init_resource::<TouchLayout>();
+        app.init_resource::<PointerHolds>();
+    }
+}
+
+/// A sprite the player can click or tap to hold an action.
+#[derive(Component)]
+pub(crate) struct Touchable {
+    action: Action,
+    /// Where the sprite sits in the keyboard layout.
+    home: Vec3,
+    half_size: Vec2,
+}
+
+impl Touchable {
+    /// A 32px oscillator or pot tile at `home`.
+    pub(crate) fn tile(action: Action, home: Vec3) -> Self {
+        Touchable {
+            action,
+            home,
+            half_size: Vec2::splat(TILE_HALF),
+        }
+    }
+
+    /// A `half_size` area around `home` that isn't moved by the touch layout.
+    pub(crate) fn area(action: Action, home: Vec3, half_size: Vec2) -> Self {
+        Touchable {
+            action,
+            home,
+            half_size,
+        }
+    }
+
+    fn transform(&self, touch: bool) -> Transform {
+        if !touch {
+            return Transform::from_translation(self.home);
+        }
+        let shift = match self.action {
+            Action::Osc(_) => TOUCH_OSC_SHIFT,
+            Action::Pot(_) => TOUCH_POT_SHIFT,
+            _ => return Transform::from_translation(self.home),
+        };
+        Transform::from_translation(
+            (self.home.truncate() * TOUCH_SCALE + shift).extend(self.home.z),
+        )
+        .with_scale(Vec3::new(TOUCH_SCALE, TOUCH_SCALE, 1.))
+    }
+
+    fn contains(&self, transform: &Transform, point: Vec2) -> bool {
+        let offset = (point - transform.translation.truncate()).abs();
+        let half = self.half_size * transform.scale.truncate();
+        offset.x <= half.x && offset.y <= half.y
+    }
+}
+
+/// Whether the controls are laid out for fingers rather than keys.
+#[derive(Resource, Default)]
+pub(crate) struct TouchLayout(pub(crate) bool);
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum Pointer {
+    Mouse,
+    Touch(u64),
+}
+
+/// Action each mouse button or finger is holding down.
+#[derive(Resource, Default)]
+struct PointerHolds(HashMap<Pointer, Action>);
+
+#[derive(Component)]
+struct PauseTabTag;
+
+fn detect_touch(touches: Res<Touches>, mut layout: ResMut<TouchLayout>) {
+    if !layout.0 && touches.any_just_pressed() {
+        info!("touch input seen, switching to the touch layout");
+        layout.0 = true;
+    }
+}
+
+/// Presses whatever is under each held pointer, so an oscillator and a pot can be held
+/// with two fingers; sliding off a sprite lets it go.
+fn pointer_actions(
+    mouse: Res<ButtonInput<MouseButton>>,
+    touches: Res<Touches>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    targets: Query<(&Touchable, &Transform, &Visibility)>,
+    mut holds: ResMut<PointerHolds>,
+    mut actions: ResMut<ButtonInput<Action>>,
+    mut times: ResMut<ActionTimes>,
+) {
+    let Ok((camera, camera_transform)) = cameras.get_single() else {
+        return;
+    };
+    let mut positions: Vec<(Pointer, Vec2)> = touches
+        .iter()
+        .map(|touch| (Pointer::Touch(touch.id()), touch.position()))
+        .collect();
+    if mouse.pressed(MouseButton::Left) {
+        if let Some(cursor) = windows.get_single().ok().and_then(Window::cursor_position) {
+            positions.push((Pointer::Mouse, cursor));
+        }
+    }
+
+    let target = |position: Vec2| {
+        let point = camera.viewport_to_world_2d(camera_transform, position)?;
+        targets
+            .iter()
+            .find(|(touchable, transform, visibility)| {
+                **visibility != Visibility::Hidden && touchable.contains(transform, point)
+            })
+            .map(|(touchable, _, _)| touchable.action)
+    };
+    let held: HashMap<Pointer, Action> = positions
+        .into_iter()
+        .filter_map(|(pointer, position)| target(position).map(|action| (pointer, action)))
+        .collect();
+
+    // presses are stamped as they are read, see `ActionTimes`
+    let now = Instant::now();
+    // an action stays down while any pointer is still on it
+    for action in holds.0.values() {
+        if !held.values().any(|other| other == action) {
+            actions.release(*action);
+        }
+    }
+    for action in held.values() {
+        if !holds.0.values().any(|other| other == action) {
+            times.press(&mut actions, *action, now);
+        }
+    }
+    holds.0 = held;
+}
+
+fn load_pause_tab(mut commands: Commands, layout: Res<TouchLayout>) {
+    commands.spawn((
+        Text2dBundle {
+            text: Text::from_section(
+                "PAUSE",
+                TextStyle {
+                    font_size: 40.,
+                    ..default()
+                },
+            ),
+            transform: Transform::from_translation(PAUSE_TAB_POS),
+            visibility: if layout.0 {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            },
+            ..default()
+        },
+        Touchable {
+            action: Action::Pause,
+            home: PAUSE_TAB_POS,
+            half_size: Vec2::new(64., 24.),
+        },
+        PauseTabTag,
+    ));
+}
+
+fn unload_pause_tab(mut commands: Commands, query: Query<Entity, With<PauseTabTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// Moves the controls between the keyboard and touch layouts, and shows the pause tab
+/// only when there are no keys to pause with.
+fn touch_layout(
+    layout: Res<TouchLayout>,
+    mut query: Query<(Ref<Touchable>, &mut Transform, Option<&mut Visibility>)>,
+) {
+    for (touchable, mut transform, visibility) in query.iter_mut() {
+        if !layout.is_changed() && !touchable.is_added() {
+            continue;
+        }
+        *transform = touchable.transform(layout.0);
+        if let (Action::Pause, Some(mut visibility)) = (touchable.action, visibility) {
+            *visibility = if layout.0 {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+}
@@ -1,9 +1,16 @@
 use bevy::{audio::Volume, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    osc::{OscState, OscType},
-    track::{Track, TrackTimer},
-    ApplicationState, ModeState, Score,
+    action::{Action, ActionSet, ActionTimes},
+    effects::SharedEffects,
+    judgment::{HoldEvent, Judgment, JudgmentEvent, TimingWindows},
+    osc::{OscSet, OscState, OscType},
+    pointer::Touchable,
+    settings::Settings,
+    synth::Voice,
+    track::{MissEvent, Track, TrackClock, TrackTime},
+    ApplicationState, ModeState,
 };
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
@@ -13,14 +20,25 @@ pub(super) struct PotPlugin;
 
 impl Plugin for PotPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(ApplicationState::Loading), load_pots.in_set(PotSet));
-        app.add_systems(Update, pot_input.in_set(PotSet));
-        app.add_systems(FixedFirst, check_note.in_set(PotSet));
-        app.add_systems(FixedUpdate, activate_pot.in_set(PotSet));
+        app.add_systems(
+            OnEnter(ApplicationState::Loading),
+            (unload_pots, load_pots, reset_hold).chain().in_set(PotSet),
+        );
+        // presses are judged before the fixed loop so a late one can't be counted a miss first
+        app.add_systems(
+            PreUpdate,
+            (pot_input, check_note)
+                .chain()
+                .in_set(PotSet)
+                .after(ActionSet)
+                .after(OscSet),
+        );
+        app.add_systems(FixedUpdate, (activate_pot, check_holds).in_set(PotSet));
         app.add_systems(OnEnter(ModeState::NotInGame), unload_pots.in_set(PotSet));
 
         app.add_event::<PotActiveEvent>();
         app.add_event::<CheckNoteEvent>();
+        app.init_resource::<ActiveHold>();
     }
 }
 
@@ -29,6 +47,7 @@ struct PotBundle {
     tag: PotTag,
     pot_type: PotType,
     state: PotState,
+    touch: Touchable,
     sprite: SpriteBundle,
 }
 
@@ -48,12 +67,14 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
     let origin_y = 0.;
 
     let potj_tex: Handle<Image> = server.load("pot_j_off.png");
+    let potj_pos = Vec3::new(origin_x, origin_y, 0.);
     let potj = PotBundle {
         tag: PotTag,
         pot_type: PotType::PotJ,
         state: PotState::Inactive,
+        touch: Touchable::tile(Action::Pot(PotType::PotJ), potj_pos),
         sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x, origin_y, 0.)),
+            transform: Transform::from_translation(potj_pos),
             texture: potj_tex,
             ..default()
         },
@@ -61,12 +82,14 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
     commands.spawn(potj);
 
     let poti_tex: Handle<Image> = server.load("pot_i_off.png");
+    let poti_pos = Vec3::new(origin_x + 16., origin_y + 32., 0.);
     let poti = PotBundle {
         tag: PotTag,
         pot_type: PotType::PotI,
         state: PotState::Inactive,
+        touch: Touchable::tile(Action::Pot(PotType::PotI), poti_pos),
         sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + 16., origin_y + 32., 0.)),
+            transform: Transform::from_translation(poti_pos),
             texture: poti_tex,
             ..default()
         },
@@ -74,12 +97,14 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
     commands.spawn(poti);
 
     let potk_tex: Handle<Image> = server.load("pot_k_off.png");
+    let potk_pos = Vec3::new(origin_x + 32., origin_y, 0.);
     let potk = PotBundle {
         tag: PotTag,
         pot_type: PotType::PotK,
         state: PotState::Inactive,
+        touch: Touchable::tile(Action::Pot(PotType::PotK), potk_pos),
         sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + 32., origin_y, 0.)),
+            transform: Transform::from_translation(potk_pos),
             texture: potk_tex,
             ..default()
         },
@@ -87,12 +112,14 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
     commands.spawn(potk);
 
     let poto_tex: Handle<Image> = server.load("pot_o_off.png");
+    let poto_pos = Vec3::new(origin_x + 48., origin_y + 32., 0.);
     let poto = PotBundle {
         tag: PotTag,
         pot_type: PotType::PotO,
         state: PotState::Inactive,
+        touch: Touchable::tile(Action::Pot(PotType::PotO), poto_pos),
         sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + 48., origin_y + 32., 0.)),
+            transform: Transform::from_translation(poto_pos),
             texture: poto_tex,
             ..default()
         },
@@ -100,12 +127,14 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
     commands.spawn(poto);
 
     let potl_tex: Handle<Image> = server.load("pot_l_off.png");
+    let potl_pos = Vec3::new(origin_x + 64., origin_y, 0.);
     let potl = PotBundle {
         tag: PotTag,
         pot_type: PotType::PotL,
         state: PotState::Inactive,
+        touch: Touchable::tile(Action::Pot(PotType::PotL), potl_pos),
         sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x + 64., origin_y, 0.)),
+            transform: Transform::from_translation(potl_pos),
             texture: potl_tex,
             ..default()
         },
@@ -114,192 +143,187 @@ fn load_pots(mut commands: Commands, server: Res<AssetServer>) {
 }
 
 fn pot_input(
-    keys: Res<ButtonInput<KeyCode>>,
+    actions: Res<ButtonInput<Action>>,
+    times: Res<ActionTimes>,
+    track_time: TrackTime,
     mut query: Query<(Entity, &PotType, &mut PotState, &mut Handle<Image>), With<PotTag>>,
     server: Res<AssetServer>,
     mut ev_activate_pot: EventWriter<PotActiveEvent>,
     mut ev_check_note: EventWriter<CheckNoteEvent>,
 ) {
     for (_entity, pot_type, mut state, mut texture) in query.iter_mut() {
-        for key in keys.get_just_pressed() {
-            match key {
-                KeyCode::KeyJ => {
-                    if pot_type == &PotType::PotJ {
-                        *state = PotState::Active;
-                        *texture = server.load("pot_j_on.png");
-                        ev_check_note.send(CheckNoteEvent);
-                        ev_activate_pot.send(PotActiveEvent(*pot_type));
-                    }
-                }
-                KeyCode::KeyI => {
-                    if pot_type == &PotType::PotI {
-                        *state = PotState::Active;
-                        *texture = server.load("pot_i_on.png");
-                        ev_check_note.send(CheckNoteEvent);
-                        ev_activate_pot.send(PotActiveEvent(*pot_type));
-                    }
-                }
-                KeyCode::KeyK => {
-                    if pot_type == &PotType::PotK {
-                        *state = PotState::Active;
-                        *texture = server.load("pot_k_on.png");
-                        ev_check_note.send(CheckNoteEvent);
-                        ev_activate_pot.send(PotActiveEvent(*pot_type));
-                    }
-                }
-                KeyCode::KeyO => {
-                    if pot_type == &PotType::PotO {
-                        *state = PotState::Active;
-                        *texture = server.load("pot_o_on.png");
-                        ev_check_note.send(CheckNoteEvent);
-                        ev_activate_pot.send(PotActiveEvent(*pot_type));
-                    }
-                }
-                KeyCode::KeyL => {
-                    if pot_type == &PotType::PotL {
-                        *state = PotState::Active;
-                        *texture = server.load("pot_l_on.png");
-                        ev_check_note.send(CheckNoteEvent);
-                        ev_activate_pot.send(PotActiveEvent(*pot_type));
-                    }
-                }
-                _ => {}
-            }
+        let action = Action::Pot(*pot_type);
+        if actions.just_pressed(action) {
+            *state = PotState::Active;
+            *texture = server.load(fetch_pot_tex(*pot_type));
+            let secs = times
+                .pressed_at(action)
+                .map_or_else(|| track_time.now(), |at| track_time.secs_at(at));
+            ev_check_note.send(CheckNoteEvent { secs });
+            ev_activate_pot.send(PotActiveEvent(*pot_type));
         }
-        for key in keys.get_just_released() {
-            match key {
-                KeyCode::KeyJ => {
-                    if pot_type == &PotType::PotJ {
-                        *state = PotState::Inactive;
-                        *texture = server.load("pot_j_off.png");
-                    }
-                }
-                KeyCode::KeyI => {
-                    if pot_type == &PotType::PotI {
-                        *state = PotState::Inactive;
-                        *texture = server.load("pot_i_off.png");
-                    }
-                }
-                KeyCode::KeyK => {
-                    if pot_type == &PotType::PotK {
-                        *state = PotState::Inactive;
-                        *texture = server.load("pot_k_off.png");
-                    }
-                }
-                KeyCode::KeyO => {
-                    if pot_type == &PotType::PotO {
-                        *state = PotState::Inactive;
-                        *texture = server.load("pot_o_off.png");
-                    }
-                }
-                KeyCode::KeyL => {
-                    if pot_type == &PotType::PotL {
-                        *state = PotState::Inactive;
-                        *texture = server.load("pot_l_off.png");
-                    }
-                }
-                _ => {}
-            }
+        // a pot let go while input was paused is released as soon as it resumes
+        if !actions.pressed(action) && *state == PotState::Active {
+            *state = PotState::Inactive;
+            *texture = server.load(fetch_pot_off_tex(*pot_type));
         }
     }
 }
 
+fn fetch_pot_off_tex(pot_type: PotType) -> String {
+    match pot_type {
+        PotType::PotJ => "pot_j_off.png",
+        PotType::PotI => "pot_i_off.png",
+        PotType::PotK => "pot_k_off.png",
+        PotType::PotO => "pot_o_off.png",
+        PotType::PotL => "pot_l_off.png",
+    }
+    .into()
+}
+
 #[allow(dead_code)]
 #[derive(Event)]
 pub(crate) struct PotActiveEvent(pub(crate) PotType);
 
 fn activate_pot(
     mut ev_activate_pot: EventReader<PotActiveEvent>,
-    timer_query: Query<&TrackTimer>,
+    clock: Res<TrackClock>,
     track: Res<Track>,
     osc_query: Query<(&OscType, &OscState)>,
     mut commands: Commands,
-    server: Res<AssetServer>,
+    mut voices: ResMut<Assets<Voice>>,
+    effects: Res<SharedEffects>,
 ) {
     for pot_ev in ev_activate_pot.read() {
-        for timer in timer_query.iter() {
-            let current_frame = (timer.timer.elapsed_secs_f64() / track.bpm).floor() as u64;
-            let current_time = track.seq[track.pos].time + (8 * track.iteration);
-            if current_frame == current_time {
-                for (o_type, o_state) in osc_query.iter() {
-                    if *o_state == OscState::Active {
-                        let osc = match o_type {
-                            OscType::Sine => "sine_".to_string(),
-                            OscType::Triangle => "triangle_".to_string(),
-                            OscType::Square => "square_".to_string(),
-                            OscType::Sawtooth => "saw_".to_string(),
-                        };
-                        let pot = match pot_ev.0 {
-                            PotType::PotJ => "a.ogg".to_string(),
-                            PotType::PotI => "b.ogg".to_string(),
-                            PotType::PotK => "c.ogg".to_string(),
-                            PotType::PotO => "d.ogg".to_string(),
-                            PotType::PotL => "e.ogg".to_string(),
-                        };
-                        commands.spawn(AudioBundle {
-                            source: server.load(format!("{}{}", osc, pot)),
+        let sequencer = &track.sequencer;
+        if sequencer.tick_at(clock.elapsed_secs()) == sequencer.note_tick(sequencer.current()) {
+            // chords layer one voice per held oscillator, split across the same level
+            let layers = osc_query
+                .iter()
+                .filter(|(_, o_state)| **o_state == OscState::Active)
+                .count()
+                .max(1);
+            for (o_type, o_state) in osc_query.iter() {
+                if *o_state == OscState::Active {
+                    let voice = Voice::new(*o_type, track.key.frequency(pot_ev.0), &effects);
+                    commands.spawn((
+                        voice.gate(pot_ev.0),
+                        AudioSourceBundle {
+                            source: voices.add(voice),
                             settings: PlaybackSettings {
-                                volume: Volume::new(0.3),
-                                ..default()
+                                volume: Volume::new(0.3 / layers as f32),
+                                ..PlaybackSettings::DESPAWN
                             },
-                        });
-                    }
+                        },
+                    ));
                 }
             }
         }
     }
 }
 
-#[allow(dead_code)]
 #[derive(Event)]
-pub(crate) struct CheckNoteEvent;
+pub(crate) struct CheckNoteEvent {
+    /// Track time of the press.
+    pub(crate) secs: f64,
+}
 
-#[allow(clippy::comparison_chain)]
 fn check_note(
     mut ev_check_note: EventReader<CheckNoteEvent>,
-    track: ResMut<Track>,
-    timer_query: Query<&TrackTimer>,
+    mut track: ResMut<Track>,
+    windows: Res<TimingWindows>,
+    settings: Res<Settings>,
     pot_active_query: Query<(&PotState, &PotType)>,
     osc_active_query: Query<(&OscState, &OscType)>,
-    mut score: ResMut<Score>,
+    mut ev_judgment: EventWriter<JudgmentEvent>,
+    mut ev_miss: EventWriter<MissEvent>,
+    mut hold: ResMut<ActiveHold>,
 ) {
-    for _ev in ev_check_note.read() {
-        if !track.seq.is_empty() {
-            let bpm = track.bpm;
-            for track_timer in timer_query.iter() {
-                let current_time = track.seq[track.pos].time + (track.iteration * 8);
-                let current_frame = (track_timer.timer.elapsed_secs_f64() / bpm).floor() as u64;
-                println!("frame: {} time: {}", current_frame, current_time);
-                if current_frame == current_time {
-                    for (p_state, p_type) in pot_active_query.iter() {
-                        if track.seq[track.pos].note.pot == *p_type && *p_state == PotState::Active
-                        {
-                            for (o_state, o_type) in osc_active_query.iter() {
-                                if track.seq[track.pos].note.s1 == *o_type
-                                    && *o_state == OscState::Active
-                                // && (current_frame == head.time
-                                //     || current_frame <= head.time + 5
-                                //     || current_frame >= (head.time - 5).clamp(5, 65536))
-                                {
-                                    println!("success");
-                                    score.value += 1;
-                                    score.updated = true;
-                                }
-                            }
-                        }
-                    }
-                    // track.pos += 1;
-                    // if track.pos > 7 {
-                    //     track.pos = 0;
-                    //     track.iteration += 1;
-                    // }
-                } else if current_frame > current_time {
-                    println!("too late");
-                } else {
-                    println!("invalid timing");
-                }
-            }
+    for ev in ev_check_note.read() {
+        let now = ev.secs - settings.audio_offset_secs();
+        // notes this press is too late for are missed, not passed over
+        for (step, offset) in track.sequencer.expire(now, &windows) {
+            ev_judgment.send(JudgmentEvent {
+                judgment: Judgment::Miss,
+                offset,
+            });
+            ev_miss.send(MissEvent {
+                tick: track.sequencer.note_tick(step),
+            });
+        }
+        let Some((step, offset)) = track.sequencer.claim(now, &windows) else {
+            continue;
+        };
+
+        let note = &track.seq[step.index].note;
+        let pot_hit = pot_active_query
+            .iter()
+            .any(|(p_state, p_type)| *p_type == note.pot && *p_state == PotState::Active);
+        let osc_hit = note.oscs().all(|osc| {
+            osc_active_query
+                .iter()
+                .any(|(o_state, o_type)| *o_type == osc && *o_state == OscState::Active)
+        });
+        let judgment = windows.evaluate(offset, pot_hit && osc_hit);
+
+        if judgment != Judgment::Miss && track.sequencer.hold(step) > 0 {
+            hold.0 = Some(Hold {
+                pot: note.pot,
+                oscs: note.oscs().collect(),
+                start_secs: track.sequencer.note_secs(step),
+                end_secs: track.sequencer.hold_end_secs(step),
+            });
         }
+        ev_judgment.send(JudgmentEvent { judgment, offset });
+    }
+}
+
+struct Hold {
+    pot: PotType,
+    oscs: Vec<OscType>,
+    start_secs: f64,
+    end_secs: f64,
+}
+
+/// Hold note currently being sustained, if any.
+#[derive(Resource, Default)]
+struct ActiveHold(Option<Hold>);
+
+fn reset_hold(mut hold: ResMut<ActiveHold>) {
+    hold.0 = None;
+}
+
+fn check_holds(
+    mut hold: ResMut<ActiveHold>,
+    clock: Res<TrackClock>,
+    settings: Res<Settings>,
+    pot_active_query: Query<(&PotState, &PotType)>,
+    osc_active_query: Query<(&OscState, &OscType)>,
+    mut ev_hold: EventWriter<HoldEvent>,
+) {
+    let Some(active) = &hold.0 else {
+        return;
+    };
+    // hold times are on the chart, so they're compared as notes are
+    let now = clock.elapsed_secs() - settings.audio_offset_secs();
+    let held = pot_active_query
+        .iter()
+        .any(|(p_state, p_type)| *p_type == active.pot && *p_state == PotState::Active)
+        && active.oscs.iter().all(|osc| {
+            osc_active_query
+                .iter()
+                .any(|(o_state, o_type)| o_type == osc && *o_state == OscState::Active)
+        });
+
+    if now >= active.end_secs {
+        ev_hold.send(HoldEvent { completion: 1. });
+        hold.0 = None;
+    } else if !held {
+        let completion = (now - active.start_secs) / (active.end_secs - active.start_secs);
+        ev_hold.send(HoldEvent {
+            completion: completion.clamp(0., 1.),
+        });
+        hold.0 = None;
     }
 }
 
@@ -312,7 +336,7 @@ fn unload_pots(mut commands: Commands, query: Query<Entity, With<PotTag>>) {
 #[derive(Component)]
 struct PotTag;
 
-#[derive(Component, PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(Component, PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
 pub(crate) enum PotType {
     PotJ,
     PotI,
@@ -322,7 +346,7 @@ pub(crate) enum PotType {
 }
 
 #[derive(Component, PartialEq, Eq, Clone, Copy)]
-enum PotState {
+pub(crate) enum PotState {
     Active,
     Inactive,
 }
@@ -0,0 +1,756 @@
+//! Rules of play with no engine in them: where the song is, which note a press belongs
+//! to, how it is judged and what it scores. The track, pot and judgment systems feed in
+//! clock times and held inputs, and act on what comes back.
+
+use crate::tempo::TempoMap;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
+pub(crate) enum Judgment {
+    Perfect,
+    Great,
+    Good,
+    Miss,
+}
+
+impl Judgment {
+    pub(crate) fn points(self) -> u64 {
+        match self {
+            Judgment::Perfect => 300,
+            Judgment::Great => 200,
+            Judgment::Good => 100,
+            Judgment::Miss => 0,
+        }
+    }
+}
+
+/// Half-widths of each judgment window, in milliseconds either side of the note.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct TimingWindows {
+    pub(crate) perfect_ms: f64,
+    pub(crate) great_ms: f64,
+    pub(crate) good_ms: f64,
+}
+
+impl Default for TimingWindows {
+    fn default() -> Self {
+        TimingWindows {
+            perfect_ms: 45.,
+            great_ms: 90.,
+            good_ms: 135.,
+        }
+    }
+}
+
+impl TimingWindows {
+    /// Whether a press `offset` seconds from a note is close enough to be judged.
+    pub(crate) fn contains(&self, offset: f64) -> bool {
+        offset.abs() * 1000. <= self.good_ms
+    }
+
+    /// Whether a press `offset` seconds from a note is too late for it.
+    pub(crate) fn passed(&self, offset: f64) -> bool {
+        offset * 1000. > self.good_ms
+    }
+
+    pub(crate) fn judge(&self, offset: f64) -> Judgment {
+        let ms = offset.abs() * 1000.;
+        if ms <= self.perfect_ms {
+            Judgment::Perfect
+        } else if ms <= self.great_ms {
+            Judgment::Great
+        } else if ms <= self.good_ms {
+            Judgment::Good
+        } else {
+            Judgment::Miss
+        }
+    }
+
+    /// Judgment for a press `offset` seconds from its note; holding the wrong oscillators
+    /// or pot is a miss however well timed.
+    pub(crate) fn evaluate(&self, offset: f64, matched: bool) -> Judgment {
+        if matched {
+            self.judge(offset)
+        } else {
+            Judgment::Miss
+        }
+    }
+}
+
+/// A step's place in the song: the loop it plays in and its index in the sequence.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Default)]
+pub(crate) struct Step {
+    pub(crate) iteration: u64,
+    pub(crate) index: usize,
+}
+
+impl Step {
+    pub(crate) fn new(index: usize, iteration: u64) -> Self {
+        Step { iteration, index }
+    }
+}
+
+/// Playback position through a looping sequence, and how far through it notes have been
+/// judged.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Sequencer {
+    tempo_map: TempoMap,
+    /// Tick each step lands on within a loop, increasing.
+    times: Vec<u64>,
+    /// Ticks each step is held for after it is hit; 0 for a tap.
+    holds: Vec<u64>,
+    /// Number of ticks in one loop of the sequence.
+    steps: u64,
+    /// Number of times the sequence plays before the song ends.
+    loops: u64,
+    current: Step,
+    /// Absolute tick of the most recently judged note.
+    judged: Option<u64>,
+}
+
+impl Sequencer {
+    /// `timings` are each step's tick within the loop and its hold length, in order.
+    pub(crate) fn new(
+        tempo_map: TempoMap,
+        steps: u64,
+        loops: u64,
+        timings: impl IntoIterator<Item = (u64, u64)>,
+    ) -> Self {
+        let (times, holds) = timings.into_iter().unzip();
+        Sequencer {
+            tempo_map,
+            times,
+            holds,
+            steps,
+            loops,
+            ..Sequencer::default()
+        }
+    }
+
+    pub(crate) fn tempo_map(&self) -> &TempoMap {
+        &self.tempo_map
+    }
+
+    pub(crate) fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// The step playback last reached.
+    pub(crate) fn current(&self) -> Step {
+        self.current
+    }
+
+    /// Back to the first step, with nothing judged.
+    pub(crate) fn rewind(&mut self) {
+        self.current = Step::default();
+        self.judged = None;
+    }
+
+    /// Tick reached after `secs` of playback.
+    pub(crate) fn tick_at(&self, secs: f64) -> u64 {
+        self.tempo_map.secs_to_ticks(secs).floor() as u64
+    }
+
+    /// Whether every loop of the sequence has been played through.
+    pub(crate) fn finished(&self) -> bool {
+        self.current.iteration >= self.loops
+    }
+
+    /// Whether `step` is part of the song rather than past its end.
+    pub(crate) fn is_step(&self, step: Step) -> bool {
+        step.iteration < self.loops
+    }
+
+    /// Absolute tick of the final note of the song, if it has any.
+    pub(crate) fn last_tick(&self) -> Option<u64> {
+        if self.is_empty() || self.loops == 0 {
+            return None;
+        }
+        Some(self.note_tick(Step::new(self.len() - 1, self.loops - 1)))
+    }
+
+    /// Absolute tick of `step`, counting completed loops.
+    pub(crate) fn note_tick(&self, step: Step) -> u64 {
+        self.times[step.index] + self.steps * step.iteration
+    }
+
+    /// Playback time in seconds of `step`.
+    pub(crate) fn note_secs(&self, step: Step) -> f64 {
+        self.tempo_map.ticks_to_secs(self.note_tick(step) as f64)
+    }
+
+    pub(crate) fn hold(&self, step: Step) -> u64 {
+        self.holds[step.index]
+    }
+
+    /// Playback time in seconds at which the hold of `step` ends.
+    pub(crate) fn hold_end_secs(&self, step: Step) -> f64 {
+        let end = self.note_tick(step) + self.hold(step);
+        self.tempo_map.ticks_to_secs(end as f64)
+    }
+
+    /// Step before the current one, wrapping into the previous loop.
+    pub(crate) fn previous_step(&self) -> Option<Step> {
+        self.step_before(self.current)
+    }
+
+    /// Step after the current one, wrapping into the next loop.
+    pub(crate) fn next_step(&self) -> Step {
+        if self.current.index + 1 < self.len() {
+            Step::new(self.current.index + 1, self.current.iteration)
+        } else {
+            Step::new(0, self.current.iteration + 1)
+        }
+    }
+
+    fn step_before(&self, step: Step) -> Option<Step> {
+        if step.index > 0 {
+            Some(Step::new(step.index - 1, step.iteration))
+        } else if step.iteration > 0 {
+            Some(Step::new(self.len() - 1, step.iteration - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Moves on a step once playback at `secs` has passed the current one. Returns the
+    /// step moved to, unless that ran off the end of the song.
+    pub(crate) fn advance(&mut self, secs: f64) -> Option<Step> {
+        if self.is_empty() || self.finished() || self.tick_at(secs) <= self.note_tick(self.current)
+        {
+            return None;
+        }
+        self.current = self.next_step();
+        (!self.finished()).then_some(self.current)
+    }
+
+    /// Picks up where `previous` left off for a chart swapped in mid-song: notes up to the
+    /// last one `previous` judged stay judged, and playback catches up to `secs`.
+    pub(crate) fn resume(&mut self, previous: &Sequencer, secs: f64) {
+        self.judged = previous.judged;
+        while self.advance(secs).is_some() {}
+    }
+
+    /// Jumps a loop ahead, keeping to the same step.
+    pub(crate) fn skip_loop(&mut self) {
+        self.current.iteration += 1;
+    }
+
+    /// The step a press at `secs` is for, and how far off it was; that step counts as
+    /// judged from then on. Notes the press is too late for should be [`expire`]d first,
+    /// or a hit on the next note would pass over them.
+    ///
+    /// [`expire`]: Sequencer::expire
+    ///
+    /// A press may be late for the previous step or early for the next one, which the
+    /// clock may not have reached yet. Notes are judged in order, so the oldest wins.
+    pub(crate) fn claim(&mut self, secs: f64, windows: &TimingWindows) -> Option<(Step, f64)> {
+        if self.is_empty() {
+            return None;
+        }
+        let claimed = [
+            self.previous_step(),
+            Some(self.current),
+            Some(self.next_step()),
+        ]
+        .into_iter()
+        .flatten()
+        .filter(|&step| self.is_step(step))
+        .filter(|&step| self.judged < Some(self.note_tick(step)))
+        .map(|step| (step, secs - self.note_secs(step)))
+        .find(|&(_, offset)| windows.contains(offset))?;
+        self.judged = Some(self.note_tick(claimed.0));
+        Some(claimed)
+    }
+
+    /// Steps whose windows closed by `secs` without a press, oldest first, each with how
+    /// late `secs` is for it; they count as judged from then on.
+    pub(crate) fn expire(&mut self, secs: f64, windows: &TimingWindows) -> Vec<(Step, f64)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let mut unjudged = Vec::new();
+        let mut step = if self.is_step(self.current) {
+            Some(self.current)
+        } else {
+            self.previous_step()
+        };
+        while let Some(before) = step {
+            if self.judged >= Some(self.note_tick(before)) {
+                break;
+            }
+            unjudged.push(before);
+            step = self.step_before(before);
+        }
+
+        let mut missed = Vec::new();
+        for step in unjudged.into_iter().rev() {
+            let offset = secs - self.note_secs(step);
+            if !windows.passed(offset) {
+                break;
+            }
+            self.judged = Some(self.note_tick(step));
+            missed.push((step, offset));
+        }
+        missed
+    }
+
+    /// Whether every loop has played and its final note has been judged.
+    pub(crate) fn complete(&self) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        self.finished() && self.judged >= self.last_tick()
+    }
+}
+
+/// Running score and combo.
+#[derive(Clone, Copy, Default, Debug)]
+pub(crate) struct Tally {
+    pub(crate) value: u64,
+    pub(crate) combo: u64,
+    pub(crate) max_combo: u64,
+}
+
+impl Tally {
+    /// Combo needed to reach each multiplier step above x1.
+    const COMBO_TIERS: [u64; 3] = [10, 25, 50];
+
+    pub(crate) fn multiplier(&self) -> u64 {
+        1 + Self::COMBO_TIERS
+            .iter()
+            .filter(|&&tier| self.combo >= tier)
+            .count() as u64
+    }
+
+    pub(crate) fn record(&mut self, judgment: Judgment) {
+        if judgment == Judgment::Miss {
+            self.combo = 0;
+        } else {
+            self.combo += 1;
+            self.max_combo = self.max_combo.max(self.combo);
+        }
+        self.value += judgment.points() * self.multiplier();
+    }
+
+    /// Awards a share of a perfect hit for the part of a hold that was sustained.
+    pub(crate) fn record_hold(&mut self, completion: f64) {
+        let points = (Judgment::Perfect.points() as f64 * completion).round() as u64;
+        self.value += points * self.multiplier();
+    }
+}
+
+#[derive(Default, Debug)]
+pub(crate) struct JudgmentStats {
+    pub(crate) perfect: u64,
+    pub(crate) great: u64,
+    pub(crate) good: u64,
+    pub(crate) miss: u64,
+}
+
+impl JudgmentStats {
+    pub(crate) fn total(&self) -> u64 {
+        self.perfect + self.great + self.good + self.miss
+    }
+
+    /// Share of the best possible judgment points earned, between 0 and 1.
+    pub(crate) fn accuracy(&self) -> f64 {
+        if self.total() == 0 {
+            return 0.;
+        }
+        let earned = self.perfect * Judgment::Perfect.points()
+            + self.great * Judgment::Great.points()
+            + self.good * Judgment::Good.points();
+        earned as f64 / (self.total() * Judgment::Perfect.points()) as f64
+    }
+
+    pub(crate) fn grade(&self) -> &'static str {
+        match self.accuracy() {
+            a if a >= 0.95 => "S",
+            a if a >= 0.9 => "A",
+            a if a >= 0.8 => "B",
+            a if a >= 0.7 => "C",
+            a if a >= 0.6 => "D",
+            _ => "F",
+        }
+    }
+
+    pub(crate) fn record(&mut self, judgment: Judgment) {
+        match judgment {
+            Judgment::Perfect => self.perfect += 1,
+            Judgment::Great => self.great += 1,
+            Judgment::Good => self.good += 1,
+            Judgment::Miss => self.miss += 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::tempo::Tempo;
+
+    const EPSILON: f64 = 1e-9;
+
+    /// Sequencer at `bpm` in quarter-note ticks, so a tick lasts `60 / bpm` seconds.
+    fn sequencer(bpm: f64, steps: u64, loops: u64, times: &[u64]) -> Sequencer {
+        let tempo = Tempo {
+            bpm,
+            ..Tempo::default()
+        };
+        Sequencer::new(
+            TempoMap::constant(tempo),
+            steps,
+            loops,
+            times.iter().map(|&time| (time, 0)),
+        )
+    }
+
+    /// Four ticks a loop at half a second each, with notes on ticks 1, 2 and 3.
+    fn three_notes(loops: u64) -> Sequencer {
+        sequencer(120., 4, loops, &[1, 2, 3])
+    }
+
+    #[test]
+    fn windows_judge_by_distance_from_the_note() {
+        let windows = TimingWindows::default();
+        assert_eq!(windows.judge(0.), Judgment::Perfect);
+        assert_eq!(windows.judge(-0.045), Judgment::Perfect);
+        assert_eq!(windows.judge(0.06), Judgment::Great);
+        assert_eq!(windows.judge(-0.1), Judgment::Good);
+        assert_eq!(windows.judge(0.2), Judgment::Miss);
+        assert!(windows.contains(0.135));
+        assert!(!windows.contains(-0.136));
+    }
+
+    #[test]
+    fn wrong_inputs_miss_however_well_timed() {
+        let windows = TimingWindows::default();
+        assert_eq!(windows.evaluate(0., true), Judgment::Perfect);
+        assert_eq!(windows.evaluate(0., false), Judgment::Miss);
+    }
+
+    #[test]
+    fn advance_moves_one_step_once_its_tick_has_passed() {
+        let mut seq = three_notes(2);
+        assert_eq!(seq.current(), Step::new(0, 0));
+        assert_eq!(seq.advance(0.9), None);
+        assert_eq!(seq.advance(1.), Some(Step::new(1, 0)));
+        assert_eq!(seq.advance(1.2), None);
+        assert_eq!(seq.advance(1.5), Some(Step::new(2, 0)));
+        assert_eq!(seq.advance(2.5), Some(Step::new(0, 1)));
+        assert_eq!(seq.note_tick(seq.current()), 5);
+    }
+
+    #[test]
+    fn advance_stops_at_the_end_of_the_song() {
+        let mut seq = three_notes(1);
+        seq.advance(1.);
+        seq.advance(1.5);
+        assert_eq!(seq.advance(2.), None);
+        assert!(seq.finished());
+        assert_eq!(seq.advance(10.), None);
+    }
+
+    #[test]
+    fn holds_end_after_their_length() {
+        let mut seq = Sequencer::new(TempoMap::constant(Tempo::default()), 4, 1, [(0, 0), (1, 2)]);
+        assert_eq!(seq.hold(Step::new(1, 0)), 2);
+        assert!((seq.hold_end_secs(Step::new(1, 0)) - 1.5).abs() < EPSILON);
+        seq.rewind();
+        assert_eq!(seq.current(), Step::default());
+    }
+
+    #[test]
+    fn claim_takes_late_and_early_presses() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        seq.advance(1.);
+
+        // late for the first note, which the clock has already left behind
+        let (step, offset) = seq.claim(0.6, &windows).unwrap();
+        assert_eq!(step, Step::new(0, 0));
+        assert!((offset - 0.1).abs() < EPSILON);
+
+        // early for the second, before the clock has reached it
+        let (step, offset) = seq.claim(0.95, &windows).unwrap();
+        assert_eq!(step, Step::new(1, 0));
+        assert!((offset + 0.05).abs() < EPSILON);
+
+        assert_eq!(seq.judged, Some(2));
+        assert_eq!(seq.claim(1., &windows), None);
+    }
+
+    #[test]
+    fn claim_ignores_presses_between_notes() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        assert_eq!(seq.claim(0.75, &windows), None);
+        assert_eq!(seq.judged, None);
+    }
+
+    #[test]
+    fn claim_stops_at_the_end_of_the_song() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        for secs in [1., 1.5, 2.] {
+            seq.advance(secs);
+        }
+        seq.claim(1.5, &windows);
+        // the next loop isn't part of the song
+        assert_eq!(seq.claim(2.5, &windows), None);
+    }
+
+    #[test]
+    fn expire_misses_notes_whose_window_has_closed() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        seq.advance(1.);
+        seq.advance(1.5);
+
+        let missed = seq.expire(1.55, &windows);
+        assert_eq!(missed.len(), 2);
+        assert_eq!(missed[0].0, Step::new(0, 0));
+        assert!((missed[0].1 - 1.05).abs() < EPSILON);
+        assert_eq!(missed[1].0, Step::new(1, 0));
+        assert_eq!(seq.judged, Some(2));
+        assert!(seq.expire(1.55, &windows).is_empty());
+    }
+
+    #[test]
+    fn expire_leaves_notes_that_can_still_be_hit() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        assert!(seq.expire(0.6, &windows).is_empty());
+        seq.advance(1.);
+        // the second note hasn't come round yet
+        assert!(seq.expire(0.6, &windows).is_empty());
+        assert_eq!(seq.claim(0.6, &windows).unwrap().0, Step::new(0, 0));
+    }
+
+    #[test]
+    fn expire_before_an_early_press_misses_the_note_it_skips() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        seq.advance(1.);
+
+        let missed = seq.expire(0.9, &windows);
+        assert_eq!(missed.len(), 1);
+        assert_eq!(missed[0].0, Step::new(0, 0));
+        assert_eq!(seq.claim(0.9, &windows).unwrap().0, Step::new(1, 0));
+    }
+
+    #[test]
+    fn song_completes_once_the_last_note_is_judged() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(1);
+        for secs in [1., 1.5, 2.] {
+            seq.advance(secs);
+        }
+        assert!(seq.finished());
+        seq.claim(0.5, &windows);
+        seq.claim(1., &windows);
+        assert!(!seq.complete());
+        seq.claim(1.5, &windows);
+        assert!(seq.complete());
+    }
+
+    #[test]
+    fn resume_keeps_judged_notes_judged() {
+        let windows = TimingWindows::default();
+        let mut seq = three_notes(2);
+        for secs in [0.5, 1., 1.5, 2.5] {
+            seq.advance(secs);
+            seq.claim(secs, &windows);
+        }
+
+        let mut reloaded = three_notes(2);
+        reloaded.resume(&seq, 2.6);
+        assert_eq!(reloaded.current(), Step::new(0, 1));
+        assert!(reloaded.expire(2.8, &windows).is_empty());
+        assert_eq!(reloaded.claim(2.95, &windows).unwrap().0, Step::new(1, 1));
+    }
+
+    #[test]
+    fn default_sequencer_has_no_song_to_complete() {
+        let windows = TimingWindows::default();
+        let mut seq = Sequencer::default();
+        assert!(seq.finished());
+        assert_eq!(seq.last_tick(), None);
+        assert!(!seq.complete());
+        assert_eq!(seq.advance(1.), None);
+        assert_eq!(seq.claim(1., &windows), None);
+        assert!(seq.expire(1., &windows).is_empty());
+    }
+
+    #[test]
+    fn combo_raises_the_multiplier_until_a_miss() {
+        let mut tally = Tally::default();
+        for _ in 0..10 {
+            tally.record(Judgment::Perfect);
+        }
+        // the tenth hit already counts at x2
+        assert_eq!(tally.multiplier(), 2);
+        assert_eq!(tally.value, 9 * 300 + 600);
+        tally.record(Judgment::Miss);
+        assert_eq!(tally.combo, 0);
+        assert_eq!(tally.max_combo, 10);
+        assert_eq!(tally.multiplier(), 1);
+    }
+
+    #[test]
+    fn holds_score_a_share_of_a_perfect() {
+        let mut tally = Tally::default();
+        tally.record_hold(0.5);
+        assert_eq!(tally.value, 150);
+        assert_eq!(tally.combo, 0);
+    }
+
+    #[test]
+    fn stats_grade_by_accuracy() {
+        let mut stats = JudgmentStats::default();
+        assert_eq!(stats.accuracy(), 0.);
+        stats.record(Judgment::Perfect);
+        stats.record(Judgment::Perfect);
+        stats.record(Judgment::Good);
+        stats.record(Judgment::Miss);
+        assert_eq!(stats.total(), 4);
+        assert!((stats.accuracy() - 700. / 1200.).abs() < EPSILON);
+        assert_eq!(stats.grade(), "F");
+    }
+
+    /// A song with a random tempo and sequence, and for every note the offset it is played
+    /// at or `None` to leave it.
+    fn song() -> impl Strategy<Value = (Sequencer, Vec<Option<f64>>)> {
+        (60f64..240., 2u64..12, 1u64..4)
+            .prop_flat_map(|(bpm, steps, loops)| {
+                let times = prop::collection::btree_set(0..steps, 1..=steps as usize);
+                (Just(bpm), Just(steps), Just(loops), times)
+            })
+            .prop_flat_map(|(bpm, steps, loops, times)| {
+                let times: Vec<u64> = times.into_iter().collect();
+                let notes = times.len() * loops as usize;
+                // notes are at least a quarter second apart, so offsets this size can't
+                // reach a neighbour's window
+                let presses = prop::collection::vec(prop::option::of(-0.1f64..0.1), notes);
+                (Just(sequencer(bpm, steps, loops, &times)), presses)
+            })
+    }
+
+    proptest! {
+        #[test]
+        fn judging_is_symmetric_and_worsens_with_distance(a in -0.3f64..0.3, b in -0.3f64..0.3) {
+            let windows = TimingWindows::default();
+            prop_assert_eq!(windows.judge(a), windows.judge(-a));
+            prop_assert_eq!(windows.contains(a), windows.judge(a) != Judgment::Miss);
+            if a.abs() <= b.abs() {
+                prop_assert!(windows.judge(a).points() >= windows.judge(b).points());
+            }
+        }
+
+        #[test]
+        fn score_only_grows_and_combo_tracks_hits(
+            judgments in prop::collection::vec(
+                prop::sample::select(vec![
+                    Judgment::Perfect,
+                    Judgment::Great,
+                    Judgment::Good,
+                    Judgment::Miss,
+                ]),
+                0..200,
+            )
+        ) {
+            let mut tally = Tally::default();
+            let mut run = 0;
+            for judgment in judgments {
+                let before = tally.value;
+                tally.record(judgment);
+                run = if judgment == Judgment::Miss { 0 } else { run + 1 };
+                prop_assert!(tally.value >= before);
+                prop_assert_eq!(tally.combo, run);
+                prop_assert!(tally.max_combo >= tally.combo);
+                prop_assert!((1..=4).contains(&tally.multiplier()));
+            }
+        }
+
+        #[test]
+        fn advance_visits_every_step_once_in_order((mut seq, _) in song(), dt in 0.001f64..0.25) {
+            let mut visited = vec![seq.current()];
+            let mut secs = 0.;
+            while !seq.finished() {
+                secs += dt;
+                let left = seq.current();
+                if let Some(step) = seq.advance(secs) {
+                    prop_assert!(seq.tick_at(secs) > seq.note_tick(left));
+                    visited.push(step);
+                }
+            }
+            let expected: Vec<Step> = (0..seq.loops)
+                .flat_map(|iteration| (0..seq.len()).map(move |index| Step::new(index, iteration)))
+                .collect();
+            prop_assert_eq!(visited, expected);
+        }
+
+        #[test]
+        fn every_note_is_judged_exactly_once((mut seq, presses) in song()) {
+            let windows = TimingWindows::default();
+            let dt = 1. / 64.;
+            let len = seq.len();
+            let mut pending: Vec<(f64, Step, f64)> = presses
+                .iter()
+                .enumerate()
+                .filter_map(|(n, offset)| {
+                    let step = Step::new(n % len, (n / len) as u64);
+                    offset.map(|offset| (seq.note_secs(step) + offset, step, offset))
+                })
+                .collect();
+            pending.reverse();
+
+            let mut judged = Vec::new();
+            let mut secs = 0.;
+            while !seq.complete() {
+                secs += dt;
+                seq.advance(secs);
+                while let Some(&(at, step, offset)) = pending.last() {
+                    if at > secs {
+                        break;
+                    }
+                    pending.pop();
+                    // as the pot systems do, anything this press is too late for goes first
+                    for (missed, _) in seq.expire(at, &windows) {
+                        judged.push((missed, false));
+                    }
+                    let claimed = seq.claim(at, &windows);
+                    prop_assert!(claimed.is_some());
+                    let (claimed, claimed_offset) = claimed.unwrap();
+                    prop_assert_eq!(claimed, step);
+                    prop_assert!((claimed_offset - offset).abs() < EPSILON);
+                    judged.push((step, presses[step.iteration as usize * len + step.index].is_some()));
+                }
+                for (step, offset) in seq.expire(secs, &windows) {
+                    prop_assert!(!windows.contains(offset));
+                    judged.push((step, false));
+                }
+                prop_assert!(secs < 1000., "song never completed");
+            }
+
+            let steps: Vec<Step> = judged.iter().map(|&(step, _)| step).collect();
+            let mut in_order = steps.clone();
+            in_order.sort();
+            prop_assert_eq!(&steps, &in_order);
+            prop_assert_eq!(steps.len(), presses.len());
+            for (step, pressed) in judged {
+                prop_assert_eq!(pressed, presses[step.iteration as usize * len + step.index].is_some());
+            }
+        }
+    }
+}
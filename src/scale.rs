@@ -0,0 +1,167 @@
+use serde::{Deserialize, Serialize};
+
+use crate::pot::PotType;
+
+/// Intervals the pots step through, in semitones above the root.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub(crate) enum Scale {
+    Major,
+    MinorPentatonic,
+    Dorian,
+    Custom(Vec<u8>),
+}
+
+impl Scale {
+    fn intervals(&self) -> &[u8] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11],
+            Scale::MinorPentatonic => &[0, 3, 5, 7, 10],
+            Scale::Dorian => &[0, 2, 3, 5, 7, 9, 10],
+            Scale::Custom(intervals) => intervals,
+        }
+    }
+
+    /// Semitones above the root of the given scale degree, wrapping into higher octaves.
+    fn semitones(&self, degree: usize) -> i32 {
+        let intervals = self.intervals();
+        let octave = (degree / intervals.len()) as i32;
+        octave * 12 + intervals[degree % intervals.len()] as i32
+    }
+}
+
+/// Root note, scale and transposition the pots are tuned to.
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Key {
+    /// MIDI note number of the first pot.
+    pub(crate) root: u8,
+    pub(crate) scale: Scale,
+    /// Semitones added to every pot, e.g. -12 for an octave down.
+    #[serde(default)]
+    pub(crate) transpose: i32,
+}
+
+impl Default for Key {
+    /// A3 dorian, which gives the A3 to E4 the pots always played.
+    fn default() -> Self {
+        Key {
+            root: 57,
+            scale: Scale::Dorian,
+            transpose: 0,
+        }
+    }
+}
+
+impl Key {
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        let intervals = self.scale.intervals();
+        if intervals.is_empty() {
+            return Err("custom scale needs at least one interval".into());
+        }
+        if intervals.windows(2).any(|pair| pair[0] >= pair[1]) || intervals[0] != 0 {
+            return Err(format!(
+                "scale intervals must start at 0 and increase, got {:?}",
+                intervals
+            ));
+        }
+        if intervals[intervals.len() - 1] >= 12 {
+            return Err(format!(
+                "scale intervals must fit within an octave, got {:?}",
+                intervals
+            ));
+        }
+        let lowest = self.root as i32 + self.transpose;
+        let highest = lowest + self.scale.semitones(PotType::PotL.degree());
+        if lowest < 0 || highest > 127 {
+            return Err(format!(
+                "root {} transposed by {} leaves the MIDI note range",
+                self.root, self.transpose
+            ));
+        }
+        Ok(())
+    }
+
+    /// MIDI note number the pot plays.
+    pub(crate) fn note(&self, pot_type: PotType) -> i32 {
+        self.root as i32 + self.transpose + self.scale.semitones(pot_type.degree())
+    }
+
+    /// Frequency in Hz of the pot's note, in equal temperament around A4 = 440 Hz.
+    pub(crate) fn frequency(&self, pot_type: PotType) -> f32 {
+        440. * 2f32.powf((self.note(pot_type) - 69) as f32 / 12.)
+    }
+}
+
+impl PotType {
+    /// Scale degree of the pot, counting up from J.
+    fn degree(self) -> usize {
+        match self {
+            PotType::PotJ => 0,
+            PotType::PotI => 1,
+            PotType::PotK => 2,
+            PotType::PotO => 3,
+            PotType::PotL => 4,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POTS: [PotType; 5] = [
+        PotType::PotJ,
+        PotType::PotI,
+        PotType::PotK,
+        PotType::PotO,
+        PotType::PotL,
+    ];
+
+    fn key(root: u8, scale: Scale) -> Key {
+        Key {
+            root,
+            scale,
+            transpose: 0,
+        }
+    }
+
+    #[test]
+    fn a4_is_440_hz() {
+        assert_eq!(key(69, Scale::Major).frequency(PotType::PotJ), 440.);
+        assert_eq!(Key::default().frequency(PotType::PotJ), 220.);
+        let up = Key {
+            transpose: 12,
+            ..key(57, Scale::Dorian)
+        };
+        assert_eq!(up.frequency(PotType::PotJ), 440.);
+    }
+
+    #[test]
+    fn pots_climb_the_scale_degrees() {
+        let major = key(60, Scale::Major);
+        let notes: Vec<i32> = POTS.iter().map(|pot| major.note(*pot)).collect();
+        assert_eq!(notes, vec![60, 62, 64, 65, 67]);
+
+        let minor = key(57, Scale::MinorPentatonic);
+        let notes: Vec<i32> = POTS.iter().map(|pot| minor.note(*pot)).collect();
+        assert_eq!(notes, vec![57, 60, 62, 64, 67]);
+    }
+
+    #[test]
+    fn validate_rejects_unknown_roots_and_modes() {
+        let parse = |json: &str| {
+            serde_json::from_str::<Key>(json)
+                .map_err(|err| err.to_string())
+                .and_then(|key| key.validate())
+        };
+        assert!(parse(r#"{"root": 57, "scale": "Dorian"}"#).is_ok());
+        assert!(parse(r#"{"root": 57, "scale": "Lydian"}"#).is_err());
+        assert!(parse(r#"{"root": "A", "scale": "Dorian"}"#).is_err());
+        assert!(parse(r#"{"root": 300, "scale": "Dorian"}"#).is_err());
+        // the top pot would land past the last MIDI note
+        assert!(parse(r#"{"root": 125, "scale": "Major"}"#).is_err());
+        assert!(parse(r#"{"root": 57, "scale": "Dorian", "transpose": -60}"#).is_err());
+        assert!(parse(r#"{"root": 57, "scale": {"Custom": [2, 4, 7]}}"#).is_err());
+        assert!(parse(r#"{"root": 57, "scale": {"Custom": [0, 7, 5]}}"#).is_err());
+        assert!(parse(r#"{"root": 57, "scale": {"Custom": [0, 12]}}"#).is_err());
+    }
+}
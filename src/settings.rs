@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{action::KeyBindings, gamepad::PadBindings, midi::MidiBindings};
+
+/// File the settings are kept in, relative to the working directory.
+#[cfg(not(target_arch = "wasm32"))]
+const SETTINGS_PATH: &str = "settings.json";
+
+pub(super) struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Settings::load());
+    }
+}
+
+/// Player settings that persist between runs.
+#[derive(Resource, Default, Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct Settings {
+    /// How late the player hears and reacts to the music, in milliseconds.
+    pub(crate) audio_offset_ms: f64,
+    pub(crate) bindings: KeyBindings,
+    pub(crate) pad_bindings: PadBindings,
+    pub(crate) midi_bindings: MidiBindings,
+}
+
+impl Settings {
+    /// Audio offset in seconds, to subtract from the track clock before judging.
+    pub(crate) fn audio_offset_secs(&self) -> f64 {
+        self.audio_offset_ms / 1000.
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn load() -> Self {
+        let Ok(text) = std::fs::read_to_string(SETTINGS_PATH) else {
+            return Settings::default();
+        };
+        let mut settings: Settings = serde_json::from_str(&text).unwrap_or_else(|err| {
+            warn!("ignoring {}: {}", SETTINGS_PATH, err);
+            Settings::default()
+        });
+        settings.bindings.add_missing();
+        settings.pad_bindings.add_missing();
+        settings
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn save(&self) {
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|err| err.to_string())
+            .and_then(|text| std::fs::write(SETTINGS_PATH, text).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            error!("could not save {}: {}", SETTINGS_PATH, err);
+        }
+    }
+
+    // the web build has no filesystem, so settings only last for the session
+    #[cfg(target_arch = "wasm32")]
+    fn load() -> Self {
+        Settings::default()
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn save(&self) {}
+}
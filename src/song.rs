@@ -0,0 +1,119 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    audio::{AddAudioSource, Source},
+    prelude::*,
+};
+
+pub(super) struct SongPlugin;
+
+impl Plugin for SongPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Song>();
+    }
+}
+
+/// How far the audio thread has got through the backing song.
+#[derive(Default, Debug)]
+struct Progress {
+    samples: AtomicU64,
+    /// Samples per second across all channels.
+    rate: AtomicU32,
+    finished: AtomicBool,
+}
+
+/// Handle on a playing song's position, shared with the audio thread.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct SongProgress(Arc<Progress>);
+
+impl SongProgress {
+    /// Seconds of the song handed to the audio output so far, once it has started.
+    pub(crate) fn position_secs(&self) -> Option<f64> {
+        let rate = self.0.rate.load(Ordering::Relaxed);
+        (rate > 0).then(|| self.0.samples.load(Ordering::Relaxed) as f64 / rate as f64)
+    }
+
+    pub(crate) fn finished(&self) -> bool {
+        self.0.finished.load(Ordering::Relaxed)
+    }
+}
+
+/// Backing song that reports its playback position as it plays.
+#[derive(Asset, TypePath, Clone)]
+pub(crate) struct Song {
+    source: AudioSource,
+    progress: SongProgress,
+}
+
+impl Song {
+    pub(crate) fn new(source: AudioSource) -> Self {
+        Song {
+            source,
+            progress: SongProgress::default(),
+        }
+    }
+
+    pub(crate) fn progress(&self) -> SongProgress {
+        self.progress.clone()
+    }
+}
+
+impl Decodable for Song {
+    type DecoderItem = <AudioSource as Decodable>::DecoderItem;
+    type Decoder = SongDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        let inner = self.source.decoder();
+        self.progress.0.rate.store(
+            inner.sample_rate() * inner.channels() as u32,
+            Ordering::Relaxed,
+        );
+        SongDecoder {
+            inner,
+            progress: self.progress.clone(),
+        }
+    }
+}
+
+pub(crate) struct SongDecoder {
+    inner: <AudioSource as Decodable>::Decoder,
+    progress: SongProgress,
+}
+
+impl Iterator for SongDecoder {
+    type Item = <AudioSource as Decodable>::DecoderItem;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next();
+        if sample.is_some() {
+            self.progress.0.samples.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.progress.0.finished.store(true, Ordering::Relaxed);
+        }
+        sample
+    }
+}
+
+impl Source for SongDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+}
@@ -0,0 +1,262 @@
+use std::{
+    f32::consts::TAU,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bevy::{
+    audio::{AddAudioSource, Source},
+    prelude::*,
+};
+
+use crate::{
+    effects::{EffectChain, SharedEffects},
+    osc::{OscState, OscType},
+    pot::{PotState, PotType},
+};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+pub(super) struct SynthPlugin;
+
+impl Plugin for SynthPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_source::<Voice>();
+        app.add_systems(Update, release_voices);
+    }
+}
+
+/// Attack, decay and release times in seconds, with the sustain level in between.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Envelope {
+    pub(crate) attack: f32,
+    pub(crate) decay: f32,
+    pub(crate) sustain: f32,
+    pub(crate) release: f32,
+}
+
+impl Envelope {
+    pub(crate) fn preset(osc_type: OscType) -> Self {
+        match osc_type {
+            OscType::Sine => Envelope {
+                attack: 0.02,
+                decay: 0.1,
+                sustain: 0.8,
+                release: 0.3,
+            },
+            OscType::Triangle => Envelope {
+                attack: 0.01,
+                decay: 0.15,
+                sustain: 0.7,
+                release: 0.25,
+            },
+            OscType::Square => Envelope {
+                attack: 0.005,
+                decay: 0.1,
+                sustain: 0.6,
+                release: 0.15,
+            },
+            OscType::Sawtooth => Envelope {
+                attack: 0.005,
+                decay: 0.2,
+                sustain: 0.5,
+                release: 0.2,
+            },
+        }
+    }
+}
+
+/// A synthesized note that sustains until its gate is closed.
+#[derive(Asset, TypePath, Clone, Debug)]
+pub(crate) struct Voice {
+    pub(crate) osc: OscType,
+    pub(crate) frequency: f32,
+    pub(crate) envelope: Envelope,
+    gate: Arc<AtomicBool>,
+    /// Samples after which the voice releases on its own.
+    length: Option<u64>,
+    effects: SharedEffects,
+}
+
+impl Voice {
+    pub(crate) fn new(osc: OscType, frequency: f32, effects: &SharedEffects) -> Self {
+        Voice {
+            osc,
+            frequency,
+            envelope: Envelope::preset(osc),
+            gate: Arc::new(AtomicBool::new(true)),
+            length: None,
+            effects: effects.clone(),
+        }
+    }
+
+    /// Release after `secs` instead of waiting for a key to go up.
+    pub(crate) fn once(mut self, secs: f32) -> Self {
+        self.length = Some((secs * SAMPLE_RATE as f32) as u64);
+        self
+    }
+
+    /// Component that lets the voice be released once its keys go up.
+    pub(crate) fn gate(&self, pot: PotType) -> VoiceGate {
+        VoiceGate {
+            pot,
+            osc: self.osc,
+            gate: self.gate.clone(),
+        }
+    }
+}
+
+impl Decodable for Voice {
+    type DecoderItem = f32;
+    type Decoder = VoiceDecoder;
+
+    fn decoder(&self) -> Self::Decoder {
+        VoiceDecoder {
+            osc: self.osc,
+            phase: 0.,
+            step: self.frequency / SAMPLE_RATE as f32,
+            envelope: self.envelope,
+            gate: self.gate.clone(),
+            length: self.length,
+            sample: 0,
+            level: 0.,
+            released: None,
+            effects: EffectChain::new(&self.effects),
+        }
+    }
+}
+
+pub(crate) struct VoiceDecoder {
+    osc: OscType,
+    /// Position within the current period, between 0 and 1.
+    phase: f32,
+    /// Phase advanced per sample.
+    step: f32,
+    envelope: Envelope,
+    gate: Arc<AtomicBool>,
+    length: Option<u64>,
+    sample: u64,
+    level: f32,
+    /// Level and sample index at which the gate closed.
+    released: Option<(f32, u64)>,
+    effects: EffectChain,
+}
+
+impl VoiceDecoder {
+    /// Envelope level for the current sample, or `None` once the release has finished.
+    fn envelope_level(&mut self) -> Option<f32> {
+        let Envelope {
+            attack,
+            decay,
+            sustain,
+            release,
+        } = self.envelope;
+
+        let timed_out = self.length.is_some_and(|length| self.sample >= length);
+        if self.released.is_none() && (timed_out || !self.gate.load(Ordering::Relaxed)) {
+            self.released = Some((self.level, self.sample));
+        }
+        if let Some((from, at)) = self.released {
+            let t = (self.sample - at) as f32 / SAMPLE_RATE as f32;
+            return (t < release).then(|| from * (1. - t / release));
+        }
+
+        let t = self.sample as f32 / SAMPLE_RATE as f32;
+        Some(if t < attack {
+            t / attack
+        } else if t < attack + decay {
+            1. - (1. - sustain) * (t - attack) / decay
+        } else {
+            sustain
+        })
+    }
+
+    fn wave(&self) -> f32 {
+        let (phase, step) = (self.phase, self.step);
+        match self.osc {
+            OscType::Sine => (TAU * phase).sin(),
+            OscType::Triangle => 1. - 4. * (phase - 0.5).abs(),
+            OscType::Square => {
+                let naive = if phase < 0.5 { 1. } else { -1. };
+                naive + poly_blep(phase, step) - poly_blep((phase + 0.5) % 1., step)
+            }
+            OscType::Sawtooth => 2. * phase - 1. - poly_blep(phase, step),
+        }
+    }
+}
+
+/// Band-limited step correction that keeps the square and saw edges from aliasing.
+fn poly_blep(phase: f32, step: f32) -> f32 {
+    if phase < step {
+        let t = phase / step;
+        2. * t - t * t - 1.
+    } else if phase > 1. - step {
+        let t = (phase - 1.) / step;
+        t * t + 2. * t + 1.
+    } else {
+        0.
+    }
+}
+
+impl Iterator for VoiceDecoder {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Some(level) = self.envelope_level() else {
+            // let the delay ring out after the note itself has faded
+            return (!self.effects.finished()).then(|| self.effects.process(0.));
+        };
+        self.level = level;
+        let value = self.effects.process(self.wave() * level);
+
+        self.phase = (self.phase + self.step) % 1.;
+        self.sample += 1;
+        Some(value)
+    }
+}
+
+impl Source for VoiceDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[derive(Component)]
+pub(crate) struct VoiceGate {
+    pot: PotType,
+    osc: OscType,
+    gate: Arc<AtomicBool>,
+}
+
+fn release_voices(
+    voice_query: Query<&VoiceGate>,
+    pot_query: Query<(&PotType, &PotState)>,
+    osc_query: Query<(&OscType, &OscState)>,
+) {
+    for voice in voice_query.iter() {
+        let pot_held = pot_query
+            .iter()
+            .any(|(p_type, p_state)| *p_type == voice.pot && *p_state == PotState::Active);
+        let osc_held = osc_query
+            .iter()
+            .any(|(o_type, o_state)| *o_type == voice.osc && *o_state == OscState::Active);
+        if !(pot_held && osc_held) {
+            voice.gate.store(false, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,492 @@
+use serde::{Deserialize, Serialize};
+
+/// Grid a chart's ticks are laid out on, relative to a quarter note.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum Subdivision {
+    Quarter,
+    Eighth,
+    EighthTriplet,
+    Sixteenth,
+    SixteenthTriplet,
+}
+
+impl Subdivision {
+    fn ticks_per_quarter(self) -> u64 {
+        match self {
+            Subdivision::Quarter => 1,
+            Subdivision::Eighth => 2,
+            Subdivision::EighthTriplet => 3,
+            Subdivision::Sixteenth => 4,
+            Subdivision::SixteenthTriplet => 6,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct TimeSignature {
+    pub(crate) beats_per_bar: u64,
+    /// Note value that receives one beat, e.g. 4 for quarter notes.
+    pub(crate) beat_unit: u64,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct Tempo {
+    /// Beats per minute, counted in `time_signature.beat_unit` notes.
+    pub(crate) bpm: f64,
+    pub(crate) time_signature: TimeSignature,
+    pub(crate) subdivision: Subdivision,
+}
+
+impl Tempo {
+    pub(crate) fn ticks_per_beat(&self) -> f64 {
+        (self.subdivision.ticks_per_quarter() * 4) as f64 / self.time_signature.beat_unit as f64
+    }
+
+    pub(crate) fn ticks_per_bar(&self) -> f64 {
+        self.ticks_per_beat() * self.time_signature.beats_per_bar as f64
+    }
+
+    pub(crate) fn seconds_per_tick(&self) -> f64 {
+        60. / self.bpm / self.ticks_per_beat()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), String> {
+        if self.bpm.is_nan() || self.bpm <= 0. {
+            return Err(format!("bpm must be positive, got {}", self.bpm));
+        }
+        if self.time_signature.beats_per_bar == 0 {
+            return Err("time signature needs at least one beat per bar".into());
+        }
+        if !self.time_signature.beat_unit.is_power_of_two() {
+            return Err(format!(
+                "beat unit must be a power of two, got {}",
+                self.time_signature.beat_unit
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Default for Tempo {
+    fn default() -> Self {
+        Tempo {
+            bpm: 120.,
+            time_signature: TimeSignature {
+                beats_per_bar: 4,
+                beat_unit: 4,
+            },
+            subdivision: Subdivision::Quarter,
+        }
+    }
+}
+
+/// Tempo change placed at an absolute tick of the track.
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) struct TempoEvent {
+    pub(crate) tick: f64,
+    pub(crate) change: TempoChange,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub(crate) enum TempoChange {
+    /// Switch to `bpm` immediately.
+    Set { bpm: f64 },
+    /// Move linearly to `bpm` over the next `ticks` ticks.
+    Ramp { bpm: f64, ticks: f64 },
+    /// Hold the current tick for `secs` seconds, then resume at the previous tempo.
+    Stop { secs: f64 },
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum SegmentKind {
+    Constant { rate: f64 },
+    Ramp { from: f64, to: f64, ticks: f64 },
+    Stop { secs: f64 },
+}
+
+/// Stretch of the track starting at `tick`/`secs`; rates are in ticks per second.
+#[derive(PartialEq, Clone, Copy, Debug)]
+struct Segment {
+    tick: f64,
+    secs: f64,
+    kind: SegmentKind,
+}
+
+impl Segment {
+    fn secs_at(&self, tick: f64) -> f64 {
+        let dt = tick - self.tick;
+        match self.kind {
+            SegmentKind::Constant { rate } => self.secs + dt / rate,
+            SegmentKind::Ramp { from, to, ticks } => {
+                if dt <= ticks {
+                    self.secs + ramp_secs(from, to, ticks, dt)
+                } else {
+                    self.secs + ramp_secs(from, to, ticks, ticks) + (dt - ticks) / to
+                }
+            }
+            SegmentKind::Stop { secs } => self.secs + secs,
+        }
+    }
+
+    fn tick_at(&self, secs: f64) -> f64 {
+        let ds = secs - self.secs;
+        match self.kind {
+            SegmentKind::Constant { rate } => self.tick + ds * rate,
+            SegmentKind::Ramp { from, to, ticks } => {
+                let ramp_len = ramp_secs(from, to, ticks, ticks);
+                if ds <= ramp_len {
+                    self.tick + ramp_ticks(from, to, ticks, ds)
+                } else {
+                    self.tick + ticks + (ds - ramp_len) * to
+                }
+            }
+            SegmentKind::Stop { .. } => self.tick,
+        }
+    }
+
+    /// Ticks per second at `secs`, or `None` while stopped.
+    fn rate_at(&self, secs: f64) -> Option<f64> {
+        match self.kind {
+            SegmentKind::Constant { rate } => Some(rate),
+            SegmentKind::Ramp { from, to, ticks } => {
+                let dt = (self.tick_at(secs) - self.tick).min(ticks);
+                Some(from + (to - from) * dt / ticks)
+            }
+            SegmentKind::Stop { .. } => None,
+        }
+    }
+}
+
+/// Seconds spent covering the first `dt` ticks of a linear ramp from `from` to `to`.
+fn ramp_secs(from: f64, to: f64, ticks: f64, dt: f64) -> f64 {
+    let slope = (to - from) / ticks;
+    if slope == 0. {
+        dt / from
+    } else {
+        ((from + slope * dt) / from).ln() / slope
+    }
+}
+
+/// Ticks covered in the first `ds` seconds of a linear ramp from `from` to `to`.
+fn ramp_ticks(from: f64, to: f64, ticks: f64, ds: f64) -> f64 {
+    let slope = (to - from) / ticks;
+    if slope == 0. {
+        ds * from
+    } else {
+        from * ((ds * slope).exp() - 1.) / slope
+    }
+}
+
+/// Converts between wall-clock seconds and tick positions across tempo changes.
+#[derive(PartialEq, Clone, Debug)]
+pub(crate) struct TempoMap {
+    tempo: Tempo,
+    segments: Vec<Segment>,
+}
+
+impl Default for TempoMap {
+    fn default() -> Self {
+        TempoMap::constant(Tempo::default())
+    }
+}
+
+impl TempoMap {
+    pub(crate) fn constant(tempo: Tempo) -> Self {
+        TempoMap {
+            tempo,
+            segments: vec![Segment {
+                tick: 0.,
+                secs: 0.,
+                kind: SegmentKind::Constant {
+                    rate: 1. / tempo.seconds_per_tick(),
+                },
+            }],
+        }
+    }
+
+    pub(crate) fn new(tempo: Tempo, events: &[TempoEvent]) -> Result<Self, String> {
+        let mut map = TempoMap::constant(tempo);
+        let mut rate = 1. / tempo.seconds_per_tick();
+        let mut free_from = 0.;
+
+        for event in events {
+            if event.tick.is_nan() || event.tick < free_from {
+                return Err(format!(
+                    "tempo change at tick {} is out of order or overlaps an earlier ramp",
+                    event.tick
+                ));
+            }
+            // a change on the same tick as a stop takes effect once the stop has played out
+            let secs = match map.segments.last() {
+                Some(last) if last.tick == event.tick => last.secs,
+                _ => map.ticks_to_secs(event.tick),
+            };
+            match event.change {
+                TempoChange::Set { bpm } => {
+                    rate = map.rate_for(bpm)?;
+                    map.push(event.tick, secs, SegmentKind::Constant { rate });
+                    free_from = event.tick;
+                }
+                TempoChange::Ramp { bpm, ticks } => {
+                    if ticks.is_nan() || ticks <= 0. {
+                        return Err(format!(
+                            "tempo ramp must be longer than 0 ticks, got {}",
+                            ticks
+                        ));
+                    }
+                    let to = map.rate_for(bpm)?;
+                    map.push(
+                        event.tick,
+                        secs,
+                        SegmentKind::Ramp {
+                            from: rate,
+                            to,
+                            ticks,
+                        },
+                    );
+                    rate = to;
+                    free_from = event.tick + ticks;
+                }
+                TempoChange::Stop { secs: length } => {
+                    if length.is_nan() || length < 0. {
+                        return Err(format!("tempo stop cannot last {} seconds", length));
+                    }
+                    map.push(event.tick, secs, SegmentKind::Stop { secs: length });
+                    map.push(event.tick, secs + length, SegmentKind::Constant { rate });
+                    free_from = event.tick;
+                }
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub(crate) fn tempo(&self) -> &Tempo {
+        &self.tempo
+    }
+
+    /// Position in ticks after `secs` seconds of playback.
+    pub(crate) fn secs_to_ticks(&self, secs: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.secs <= secs)
+            .saturating_sub(1);
+        self.segments[index].tick_at(secs)
+    }
+
+    /// Length of a beat at the tempo playing `secs` into the track; during a stop, at the
+    /// tempo it resumes with.
+    pub(crate) fn beat_secs_at(&self, secs: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.secs <= secs)
+            .saturating_sub(1);
+        match self.segments[index].rate_at(secs) {
+            Some(rate) => self.tempo.ticks_per_beat() / rate,
+            // a stop is always followed by the segment it resumes with
+            None => self.beat_secs_at(self.segments[index + 1].secs),
+        }
+    }
+
+    /// Seconds of playback needed to reach `tick`.
+    pub(crate) fn ticks_to_secs(&self, tick: f64) -> f64 {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.tick < tick)
+            .saturating_sub(1);
+        self.segments[index].secs_at(tick)
+    }
+
+    fn rate_for(&self, bpm: f64) -> Result<f64, String> {
+        Tempo { bpm, ..self.tempo }.validate()?;
+        Ok(bpm * self.tempo.ticks_per_beat() / 60.)
+    }
+
+    fn push(&mut self, tick: f64, secs: f64, kind: SegmentKind) {
+        self.segments.push(Segment { tick, secs, kind });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1e-9;
+
+    fn tempo(bpm: f64, subdivision: Subdivision) -> Tempo {
+        Tempo {
+            bpm,
+            subdivision,
+            ..Tempo::default()
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < EPSILON,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn ticks_follow_subdivision() {
+        assert_close(tempo(120., Subdivision::Quarter).seconds_per_tick(), 0.5);
+        assert_close(tempo(120., Subdivision::Eighth).seconds_per_tick(), 0.25);
+        assert_close(
+            tempo(120., Subdivision::EighthTriplet).seconds_per_tick(),
+            0.5 / 3.,
+        );
+        assert_close(
+            tempo(120., Subdivision::Sixteenth).seconds_per_tick(),
+            0.125,
+        );
+
+        let six_eight = Tempo {
+            bpm: 120.,
+            time_signature: TimeSignature {
+                beats_per_bar: 6,
+                beat_unit: 8,
+            },
+            subdivision: Subdivision::Eighth,
+        };
+        assert_close(six_eight.ticks_per_beat(), 1.);
+        assert_close(six_eight.ticks_per_bar(), 6.);
+    }
+
+    #[test]
+    fn constant_map_converts_both_ways() {
+        let map = TempoMap::constant(tempo(120., Subdivision::Quarter));
+        assert_close(map.ticks_to_secs(4.), 2.);
+        assert_close(map.secs_to_ticks(2.), 4.);
+        assert_close(map.secs_to_ticks(0.75), 1.5);
+    }
+
+    #[test]
+    fn set_switches_tempo_at_tick() {
+        let events = [TempoEvent {
+            tick: 4.,
+            change: TempoChange::Set { bpm: 240. },
+        }];
+        let map = TempoMap::new(tempo(120., Subdivision::Quarter), &events).unwrap();
+        assert_close(map.ticks_to_secs(4.), 2.);
+        assert_close(map.ticks_to_secs(8.), 3.);
+        assert_close(map.secs_to_ticks(1.), 2.);
+        assert_close(map.secs_to_ticks(2.5), 6.);
+    }
+
+    #[test]
+    fn ramp_accelerates_smoothly() {
+        let events = [TempoEvent {
+            tick: 0.,
+            change: TempoChange::Ramp {
+                bpm: 240.,
+                ticks: 8.,
+            },
+        }];
+        let map = TempoMap::new(tempo(120., Subdivision::Quarter), &events).unwrap();
+        // 2 -> 4 ticks per second over 8 ticks: 4 ln(2) seconds.
+        let ramp_end = 4. * 2f64.ln();
+        assert_close(map.ticks_to_secs(8.), ramp_end);
+        assert_close(map.ticks_to_secs(12.), ramp_end + 1.);
+        assert!(map.ticks_to_secs(4.) > 1. && map.ticks_to_secs(4.) < 2.);
+        for tick in [0.5, 3., 7.9, 8., 10.] {
+            assert_close(map.secs_to_ticks(map.ticks_to_secs(tick)), tick);
+        }
+    }
+
+    #[test]
+    fn stop_holds_tick_then_resumes() {
+        let events = [TempoEvent {
+            tick: 4.,
+            change: TempoChange::Stop { secs: 1.5 },
+        }];
+        let map = TempoMap::new(tempo(120., Subdivision::Quarter), &events).unwrap();
+        assert_close(map.ticks_to_secs(4.), 2.);
+        assert_close(map.secs_to_ticks(2.), 4.);
+        assert_close(map.secs_to_ticks(3.), 4.);
+        assert_close(map.secs_to_ticks(3.5), 4.);
+        assert_close(map.secs_to_ticks(4.), 5.);
+        assert_close(map.ticks_to_secs(5.), 4.);
+    }
+
+    #[test]
+    fn change_on_a_stop_applies_after_it() {
+        let events = [
+            TempoEvent {
+                tick: 4.,
+                change: TempoChange::Stop { secs: 1.5 },
+            },
+            TempoEvent {
+                tick: 4.,
+                change: TempoChange::Set { bpm: 60. },
+            },
+        ];
+        let map = TempoMap::new(tempo(120., Subdivision::Quarter), &events).unwrap();
+        assert_close(map.ticks_to_secs(4.), 2.);
+        assert_close(map.secs_to_ticks(3.), 4.);
+        assert_close(map.secs_to_ticks(3.5), 4.);
+        assert_close(map.secs_to_ticks(4.5), 5.);
+        assert_close(map.ticks_to_secs(5.), 4.5);
+        assert_close(map.ticks_to_secs(6.), 5.5);
+    }
+
+    #[test]
+    fn beat_length_follows_the_tempo_in_play() {
+        let events = [
+            TempoEvent {
+                tick: 0.,
+                change: TempoChange::Ramp {
+                    bpm: 240.,
+                    ticks: 8.,
+                },
+            },
+            TempoEvent {
+                tick: 12.,
+                change: TempoChange::Stop { secs: 1.5 },
+            },
+            TempoEvent {
+                tick: 12.,
+                change: TempoChange::Set { bpm: 60. },
+            },
+        ];
+        let map = TempoMap::new(tempo(120., Subdivision::Quarter), &events).unwrap();
+        assert_close(map.beat_secs_at(0.), 0.5);
+        assert_close(map.beat_secs_at(map.ticks_to_secs(4.)), 1. / 3.);
+        assert_close(map.beat_secs_at(map.ticks_to_secs(10.)), 0.25);
+        // stopped, so the count follows the tempo after the stop
+        assert_close(map.beat_secs_at(map.ticks_to_secs(12.) + 0.5), 1.);
+    }
+
+    #[test]
+    fn rejects_overlapping_and_invalid_changes() {
+        let base = tempo(120., Subdivision::Quarter);
+        let overlapping = [
+            TempoEvent {
+                tick: 0.,
+                change: TempoChange::Ramp {
+                    bpm: 90.,
+                    ticks: 8.,
+                },
+            },
+            TempoEvent {
+                tick: 4.,
+                change: TempoChange::Set { bpm: 120. },
+            },
+        ];
+        assert!(TempoMap::new(base, &overlapping).is_err());
+
+        let zero_bpm = [TempoEvent {
+            tick: 0.,
+            change: TempoChange::Set { bpm: 0. },
+        }];
+        assert!(TempoMap::new(base, &zero_bpm).is_err());
+
+        let negative_stop = [TempoEvent {
+            tick: 0.,
+            change: TempoChange::Stop { secs: -1. },
+        }];
+        assert!(TempoMap::new(base, &negative_stop).is_err());
+    }
+}
@@ -1,10 +1,23 @@
-use bevy::{prelude::*, time::Stopwatch};
+use bevy::{
+    color::palettes::css::{ORANGE, RED},
+    ecs::system::SystemParam,
+    prelude::*,
+    sprite::Anchor,
+    utils::Instant,
+};
+use serde::{Deserialize, Serialize};
 
-#[allow(unused_imports)]
 use crate::{
+    chart::{Chart, ChartReloadedEvent},
+    effects::EffectEvent,
+    judgment::{Judgment, JudgmentEvent, TimingWindows},
     osc::{fetch_osc_tex, OscType},
     pot::{fetch_pot_tex, PotActiveEvent, PotType},
-    ApplicationState,
+    rhythm::Sequencer,
+    scale::Key,
+    settings::Settings,
+    song::{Song, SongProgress},
+    ApplicationState, ModeState, PauseState,
 };
 
 #[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
@@ -14,71 +27,224 @@ pub(super) struct TrackPlugin;
 
 impl Plugin for TrackPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(OnEnter(ApplicationState::Loading), load_track);
+        app.add_systems(OnExit(ApplicationState::Loading), load_track);
         app.add_systems(OnEnter(ApplicationState::Freeform), load_track);
+        app.add_systems(OnEnter(ApplicationState::Failed), (stop_playback, end_run));
+        app.add_systems(OnEnter(ApplicationState::Results), (stop_playback, end_run));
+        app.add_systems(OnEnter(ModeState::NotInGame), (stop_playback, unload_track));
+        app.add_systems(OnEnter(PauseState::Paused), pause_audio);
+        app.add_systems(OnEnter(PauseState::Unpaused), resume_audio);
+        app.add_systems(
+            Update,
+            (reload_track_strip, mark_misses, fade_miss_markers)
+                .run_if(in_state(ApplicationState::InGame)),
+        );
         // app.add_systems(Update, start_playback.in_set(TrackSet));
         app.add_systems(
             FixedUpdate,
-            (tick_track_timer, advance_iteration, start_playback).in_set(TrackSet),
+            (
+                tick_track_clock,
+                (tick_track_timer, advance_iteration, start_playback),
+                detect_misses,
+                check_song_end,
+            )
+                .chain()
+                .in_set(TrackSet),
         );
+        app.init_resource::<TrackClock>();
+        app.init_resource::<BackingAudio>();
         app.insert_resource(Track {
-            bpm: 0.333,
-            iteration: 0,
-            pos: 0,
-            seq: SAMPLE_SEQ_TWO.to_vec(),
+            sequencer: Sequencer::default(),
+            key: Key::default(),
+            effects: Vec::new(),
+            lead_in: 0,
+            seq: Vec::new(),
+            audio: None,
         });
         app.add_event::<AdvanceIterationEvent>();
+        app.add_event::<MissEvent>();
     }
 }
 
-#[derive(Component)]
-pub(crate) struct TrackTimer {
-    pub(crate) timer: Stopwatch,
+/// Drift past which the clock jumps straight to the song instead of easing towards it.
+const DRIFT_SNAP_SECS: f64 = 0.25;
+/// Share of the drift from the song taken out each tick.
+const DRIFT_CORRECTION: f64 = 0.1;
+
+/// Shared playback clock that the track, LED row and backing audio all follow.
+///
+/// Runs off frame time until the backing song starts, then follows the song's playback
+/// position so hitches and pauses can't pull judgment away from the music.
+#[derive(Resource, Default)]
+pub(crate) struct TrackClock {
+    secs: f64,
+    song: Option<SongProgress>,
+    /// Clock time at which the song's first sample played.
+    song_start: Option<f64>,
 }
 
-#[derive(Component)]
-struct StartDelayTimer {
-    timer: Stopwatch,
+impl TrackClock {
+    pub(crate) fn elapsed_secs(&self) -> f64 {
+        self.secs
+    }
+
+    fn reset(&mut self) {
+        *self = TrackClock::default();
+    }
+
+    fn follow(&mut self, song: SongProgress) {
+        self.song = Some(song);
+        self.song_start = None;
+    }
+}
+
+/// Reads the track clock between fixed ticks, for input that lands mid-frame.
+#[derive(SystemParam)]
+pub(crate) struct TrackTime<'w> {
+    clock: Res<'w, TrackClock>,
+    fixed: Res<'w, Time<Fixed>>,
+    virt: Res<'w, Time<Virtual>>,
+    real: Res<'w, Time<Real>>,
+}
+
+impl TrackTime<'_> {
+    /// Track time at the start of this frame.
+    pub(crate) fn now(&self) -> f64 {
+        // virtual time the fixed loop hasn't consumed yet is time the clock hasn't seen
+        let pending = self.virt.elapsed_seconds_f64() - self.fixed.elapsed_seconds_f64();
+        self.clock.elapsed_secs() + pending
+    }
+
+    /// Track time at `instant`, measured from the start of this frame.
+    pub(crate) fn secs_at(&self, instant: Instant) -> f64 {
+        let Some(frame_start) = self.real.last_update() else {
+            return self.now();
+        };
+        let since = if instant >= frame_start {
+            (instant - frame_start).as_secs_f64()
+        } else {
+            -(frame_start - instant).as_secs_f64()
+        };
+        self.now() + since * self.virt.relative_speed_f64()
+    }
+}
+
+/// Backing song for the loaded chart, fetched ahead of time so it can start on cue.
+#[derive(Resource, Default)]
+struct BackingAudio(Option<Handle<AudioSource>>);
+
+fn tick_track_clock(mut clock: ResMut<TrackClock>, time: Res<Time>) {
+    clock.secs += time.delta_seconds_f64();
+
+    let Some(position) = clock
+        .song
+        .as_ref()
+        .filter(|song| !song.finished())
+        .and_then(SongProgress::position_secs)
+    else {
+        return;
+    };
+    let now = clock.secs;
+    let song_start = *clock.song_start.get_or_insert(now - position);
+    let drift = song_start + position - clock.secs;
+    if drift.abs() > DRIFT_SNAP_SECS {
+        clock.secs += drift;
+    } else {
+        clock.secs += drift * DRIFT_CORRECTION;
+    }
 }
 
 fn tick_track_timer(
-    mut query: Query<&mut TrackTimer>,
-    time: Res<Time>,
+    clock: Res<TrackClock>,
     mut track: ResMut<Track>,
     mut ev_activate_pot: EventWriter<PotActiveEvent>,
 ) {
-    for mut track_timer in query.iter_mut() {
-        track_timer.timer.tick(time.delta());
-        let current_frame = (track_timer.timer.elapsed_secs_f64() / track.bpm).floor() as u64;
-        let current_time = track.seq[track.pos].time + (8 * track.iteration);
-        if current_frame > current_time {
-            track.pos += 1;
-            if track.pos > 7 {
-                track.pos = 0;
-                track.iteration += 1;
-            }
-            ev_activate_pot.send(PotActiveEvent(track.seq[track.pos].note.pot));
-        }
+    // nothing to step through until a chart has been applied, whatever state we're in
+    if !track.is_loaded() {
+        return;
+    }
+    let stepped = track.sequencer.advance(clock.elapsed_secs());
+    if let Some(seq) = stepped.and_then(|step| track.seq.get(step.index)) {
+        ev_activate_pot.send(PotActiveEvent(seq.note.pot));
+    }
+}
+
+fn check_song_end(track: Res<Track>, mut next_app_state: ResMut<NextState<ApplicationState>>) {
+    if track.sequencer.complete() {
+        next_app_state.set(ApplicationState::Results);
+    }
+}
+
+/// Sent when a note's timing window closes without it being played.
+#[derive(Event)]
+pub(crate) struct MissEvent {
+    pub(crate) tick: u64,
+}
+
+fn detect_misses(
+    clock: Res<TrackClock>,
+    windows: Res<TimingWindows>,
+    settings: Res<Settings>,
+    mut track: ResMut<Track>,
+    mut ev_judgment: EventWriter<JudgmentEvent>,
+    mut ev_miss: EventWriter<MissEvent>,
+) {
+    let now = clock.elapsed_secs() - settings.audio_offset_secs();
+    for (step, offset) in track.sequencer.expire(now, &windows) {
+        ev_judgment.send(JudgmentEvent {
+            judgment: Judgment::Miss,
+            offset,
+        });
+        ev_miss.send(MissEvent {
+            tick: track.sequencer.note_tick(step),
+        });
     }
 }
 
 #[derive(Component)]
-enum TrackPos {
-    A,
-    B,
-    C,
-    D,
-    E,
-    F,
-    G,
-    H,
+struct MissMarker {
+    timer: Timer,
+}
+
+/// Flags the strip column of each missed note for a moment.
+fn mark_misses(mut commands: Commands, track: Res<Track>, mut ev_miss: EventReader<MissEvent>) {
+    let steps = track.sequencer.steps();
+    for ev in ev_miss.read() {
+        let scale = step_scale(steps);
+        let y = STRIP_Y - STEP_OFFSET * scale;
+        commands.spawn((
+            SpriteBundle {
+                sprite: Sprite {
+                    color: Color::Srgba(RED),
+                    custom_size: Some(Vec2::splat(STEP_OFFSET / 2.)),
+                    ..default()
+                },
+                transform: step_transform(ev.tick % steps, steps, y, 10.),
+                ..default()
+            },
+            MissMarker {
+                timer: Timer::from_seconds(0.5, TimerMode::Once),
+            },
+        ));
+    }
+}
+
+fn fade_miss_markers(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut MissMarker)>,
+) {
+    for (entity, mut marker) in query.iter_mut() {
+        if marker.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        }
+    }
 }
 
 #[derive(Bundle)]
 struct TrackOscBundle {
     tag: TrackOscTag,
     sprite: SpriteBundle,
-    pos: TrackPos,
 }
 
 #[derive(Component)]
@@ -88,303 +254,206 @@ struct TrackOscTag;
 struct TrackPotBundle {
     tag: TrackPotTag,
     sprite: SpriteBundle,
-    pos: TrackPos,
 }
 
 fn start_playback(
     mut commands: Commands,
-    server: Res<AssetServer>,
-    time: Res<Time<Virtual>>,
+    mut clock: ResMut<TrackClock>,
     track: Res<Track>,
-    mut query: Query<&mut StartDelayTimer>,
+    backing: Res<BackingAudio>,
+    sources: Res<Assets<AudioSource>>,
+    mut songs: ResMut<Assets<Song>>,
+    query: Query<Entity, With<TrackTag>>,
 ) {
-    for mut delay_timer in query.iter_mut() {
-        if delay_timer.timer.elapsed_secs_f64() < track.bpm * 24. {
-            delay_timer.timer.tick(time.delta());
-        } else if delay_timer.timer.elapsed_secs_f64() >= track.bpm * 24. {
-            commands.spawn(AudioBundle {
-                source: server.load("tj_01.ogg"),
-                ..default()
-            });
-            delay_timer.timer.reset();
-            delay_timer.timer.pause();
-        }
+    if !query.is_empty() || clock.elapsed_secs() < track.audio_start_secs() {
+        return;
+    }
+    let Some(source) = backing.0.as_ref().and_then(|handle| sources.get(handle)) else {
+        return;
+    };
+    let song = Song::new(source.clone());
+    clock.follow(song.progress());
+    commands.spawn(TrackBundle {
+        tag: TrackTag,
+        audio: AudioSourceBundle {
+            source: songs.add(song),
+            ..default()
+        },
+    });
+}
+
+/// Holds the song and any ringing voices where they are; the clock stops with them, so
+/// they line back up with the track when play resumes.
+fn pause_audio(query: Query<&AudioSink>) {
+    for sink in query.iter() {
+        sink.pause();
     }
 }
 
+fn resume_audio(query: Query<&AudioSink>) {
+    for sink in query.iter() {
+        sink.play();
+    }
+}
+
+fn stop_playback(mut commands: Commands, query: Query<Entity, With<TrackTag>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
+
+/// A finished run can't be resumed, so the song is dropped until the next load.
+fn end_run(mut track: ResMut<Track>) {
+    track.unload();
+}
+
 #[derive(Component)]
 struct TrackPotTag;
 
 fn load_track(
     mut commands: Commands,
     server: Res<AssetServer>,
-    query: Query<Entity, With<TrackTimer>>,
+    query: Query<
+        Entity,
+        Or<(
+            With<TrackTag>,
+            With<TrackOscTag>,
+            With<TrackPotTag>,
+            With<MissMarker>,
+        )>,
+    >,
+    mut clock: ResMut<TrackClock>,
     mut track: ResMut<Track>,
 ) {
-    let origin_x = -150.;
-    let origin_y = 150.;
-    let osc_layer = 0.;
-    let pot_layer = 5.;
-    let offset = 32.;
-
     for entity in query.iter() {
         commands.entity(entity).despawn();
     }
-    if track.pos != 0 {
-        track.pos = 0;
+    track.sequencer.rewind();
+
+    clock.reset();
+    let backing = track.audio.clone().map(|audio| server.load(audio));
+    commands.insert_resource(BackingAudio(backing));
+
+    spawn_track_strip(&mut commands, &server, &track);
+}
+
+fn unload_track(
+    mut commands: Commands,
+    query: Query<Entity, Or<(With<TrackOscTag>, With<TrackPotTag>, With<MissMarker>)>>,
+) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn();
     }
-    if track.iteration != 0 {
-        track.iteration = 0;
+}
+
+fn reload_track_strip(
+    mut commands: Commands,
+    server: Res<AssetServer>,
+    track: Res<Track>,
+    mut ev_reloaded: EventReader<ChartReloadedEvent>,
+    query: Query<Entity, Or<(With<TrackOscTag>, With<TrackPotTag>)>>,
+) {
+    if ev_reloaded.read().last().is_some() {
+        for entity in query.iter() {
+            commands.entity(entity).despawn();
+        }
+        spawn_track_strip(&mut commands, &server, &track);
     }
+}
 
-    let track_osc_a = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(origin_x, origin_y, osc_layer)),
+fn spawn_track_strip(commands: &mut Commands, server: &AssetServer, track: &Track) {
+    let origin_y = STRIP_Y;
+    let osc_layer = 0.;
+    let pot_layer = 5.;
 
-            texture: server.load(fetch_osc_tex(track.seq[0].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::A,
-    },);
-    let track_pot_a = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[0].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::A,
-    };
-    commands.spawn(track_osc_a);
-    commands.spawn(track_pot_a);
-
-    let track_osc_b = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 1.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[1].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::B,
-    },);
-    let track_pot_b = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 1.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[1].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::B,
-    };
-    commands.spawn(track_osc_b);
-    commands.spawn(track_pot_b);
-
-    let track_osc_c = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 2.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[2].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::C,
-    },);
-    let track_pot_c = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 2.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[2].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::C,
-    };
-    commands.spawn(track_osc_c);
-    commands.spawn(track_pot_c);
-
-    let track_osc_d = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 3.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[3].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::D,
-    },);
-    let track_pot_d = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 3.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[3].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::D,
-    };
-    commands.spawn(track_osc_d);
-    commands.spawn(track_pot_d);
-
-    let track_osc_e = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 4.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[4].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::E,
-    },);
-    let track_pot_e = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 4.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[4].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::E,
-    };
-    commands.spawn(track_osc_e);
-    commands.spawn(track_pot_e);
-
-    let track_osc_f = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 5.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[5].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::F,
-    },);
-    let track_pot_f = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 5.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[5].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::F,
-    };
-    commands.spawn(track_osc_f);
-    commands.spawn(track_pot_f);
-
-    let track_osc_g = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 6.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[6].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::G,
-    },);
-    let track_pot_g = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 6.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[6].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::G,
-    };
-    commands.spawn(track_osc_g);
-    commands.spawn(track_pot_g);
-
-    let track_osc_h = (TrackOscBundle {
-        tag: TrackOscTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 7.,
-                origin_y,
-                osc_layer,
-            )),
-
-            texture: server.load(fetch_osc_tex(track.seq[7].note.s1)),
-            ..default()
-        },
-        pos: TrackPos::H,
-    },);
-    let track_pot_h = TrackPotBundle {
-        tag: TrackPotTag,
-        sprite: SpriteBundle {
-            transform: Transform::from_translation(Vec3::new(
-                origin_x + offset * 7.,
-                origin_y + offset,
-                pot_layer,
-            )),
-            texture: server.load(fetch_pot_tex(track.seq[7].note.pot)),
-            ..default()
-        },
-        pos: TrackPos::H,
-    };
-    commands.spawn(track_osc_h);
-    commands.spawn(track_pot_h);
+    for (index, seq) in track.seq.iter().enumerate() {
+        let column = track.column(index);
+        let osc_transform = step_transform(column, track.sequencer.steps(), origin_y, osc_layer);
+        // chords share the cell, each oscillator drawn at half size side by side
+        let chord = seq.note.s2.is_some();
+        for (slot, osc) in seq.note.oscs().enumerate() {
+            let mut transform = osc_transform;
+            if chord {
+                let half = transform.scale.x / 2.;
+                transform.translation.x += STEP_OFFSET * half * (slot as f32 - 0.5);
+                transform.scale = Vec3::new(half, half, 1.);
+            }
+            commands.spawn(TrackOscBundle {
+                tag: TrackOscTag,
+                sprite: SpriteBundle {
+                    transform,
+                    texture: server.load(fetch_osc_tex(osc)),
+                    ..default()
+                },
+            });
+        }
+        if seq.hold > 0 {
+            let scale = step_scale(track.sequencer.steps());
+            let mut transform = osc_transform;
+            transform.translation.z = osc_layer - 1.;
+            transform.scale = Vec3::ONE;
+            commands.spawn(TrackOscBundle {
+                tag: TrackOscTag,
+                sprite: SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::Srgba(ORANGE),
+                        custom_size: Some(Vec2::new(
+                            STEP_OFFSET * scale * seq.hold as f32,
+                            HOLD_TAIL_HEIGHT * scale,
+                        )),
+                        anchor: Anchor::CenterLeft,
+                        ..default()
+                    },
+                    transform,
+                    ..default()
+                },
+            });
+        }
+        let track_pot = TrackPotBundle {
+            tag: TrackPotTag,
+            sprite: SpriteBundle {
+                transform: step_transform(
+                    column,
+                    track.sequencer.steps(),
+                    origin_y + STEP_OFFSET * step_scale(track.sequencer.steps()),
+                    pot_layer,
+                ),
+                texture: server.load(fetch_pot_tex(seq.note.pot)),
+                ..default()
+            },
+        };
+        commands.spawn(track_pot);
+    }
+}
 
-    commands.spawn(TrackTimer {
-        timer: Stopwatch::new(),
-    });
-    commands.spawn(StartDelayTimer {
-        timer: Stopwatch::new(),
-    });
+/// Height of the oscillator row of the strip.
+const STRIP_Y: f32 = 150.;
+const HOLD_TAIL_HEIGHT: f32 = 8.;
+const STEP_ORIGIN_X: f32 = -150.;
+const STEP_OFFSET: f32 = 32.;
+/// Widest the strip may grow before steps are scaled down to fit.
+const MAX_STRIP_WIDTH: f32 = 512.;
+
+/// Scale applied to step sprites so that `steps` columns fit the strip.
+pub(crate) fn step_scale(steps: u64) -> f32 {
+    (MAX_STRIP_WIDTH / (STEP_OFFSET * steps as f32)).min(1.)
+}
+
+/// Position of column `column` in a row of `steps` columns at height `y`.
+pub(crate) fn step_transform(column: u64, steps: u64, y: f32, layer: f32) -> Transform {
+    let scale = step_scale(steps);
+    Transform::from_translation(Vec3::new(
+        STEP_ORIGIN_X + STEP_OFFSET * scale * column as f32,
+        y,
+        layer,
+    ))
+    .with_scale(Vec3::new(scale, scale, 1.))
 }
 
 #[derive(Bundle)]
 struct TrackBundle {
     tag: TrackTag,
-    audio: AudioBundle,
+    audio: AudioSourceBundle<Song>,
 }
 
 #[derive(Component)]
@@ -392,23 +461,84 @@ struct TrackTag;
 
 #[derive(Resource)]
 pub(crate) struct Track {
-    pub(crate) bpm: f64,
-    pub(crate) iteration: u64,
-    pub(crate) pos: usize,
+    /// Position through the sequence and which notes have been judged.
+    pub(crate) sequencer: Sequencer,
+    pub(crate) key: Key,
+    pub(crate) effects: Vec<EffectEvent>,
+    /// Bars of playback before the backing audio starts.
+    pub(crate) lead_in: u64,
     pub(crate) seq: Vec<Seq>,
+    pub(crate) audio: Option<String>,
+}
+
+impl Track {
+    pub(crate) fn apply_chart(&mut self, chart: &Chart) {
+        self.sequencer = Sequencer::new(
+            chart.tempo_map.clone(),
+            chart.steps,
+            chart.loops,
+            chart.seq.iter().map(|seq| (seq.time, seq.hold)),
+        );
+        self.key = chart.key.clone();
+        self.effects = chart.effects.clone();
+        self.lead_in = chart.lead_in;
+        self.seq = chart.seq.clone();
+        self.audio = chart.audio.clone();
+    }
+
+    /// Swaps in an edited chart `secs` into the song without judging again what has
+    /// already been played.
+    pub(crate) fn reload_chart(&mut self, chart: &Chart, secs: f64) {
+        let previous = std::mem::take(&mut self.sequencer);
+        self.apply_chart(chart);
+        self.sequencer.resume(&previous, secs);
+    }
+
+    /// Drops the song, leaving the sequencer where the run ended.
+    pub(crate) fn unload(&mut self) {
+        self.seq.clear();
+        self.effects.clear();
+        self.audio = None;
+    }
+
+    /// Whether a chart has been applied, so there is a song to play.
+    pub(crate) fn is_loaded(&self) -> bool {
+        !self.seq.is_empty()
+    }
+
+    /// Seconds of playback before the backing audio starts.
+    pub(crate) fn audio_start_secs(&self) -> f64 {
+        let tempo_map = self.sequencer.tempo_map();
+        tempo_map.ticks_to_secs(self.lead_in as f64 * tempo_map.tempo().ticks_per_bar())
+    }
+
+    /// Strip column of the step at `index`.
+    pub(crate) fn column(&self, index: usize) -> u64 {
+        self.seq[index].time
+    }
 }
 
-#[allow(dead_code)]
-#[derive(Component, Clone)]
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Note {
     pub(crate) s1: OscType,
+    /// Second oscillator that must be held alongside `s1` for a chord.
     pub(crate) s2: Option<OscType>,
     pub(crate) pot: PotType,
 }
 
-#[derive(Component, Clone)]
+impl Note {
+    /// Every oscillator the note requires.
+    pub(crate) fn oscs(&self) -> impl Iterator<Item = OscType> {
+        std::iter::once(self.s1).chain(self.s2)
+    }
+}
+
+#[derive(Component, Clone, Debug, Serialize, Deserialize)]
 pub(crate) struct Seq {
-    pub(crate) time: u64, // multiplier for current bpm frame
+    pub(crate) time: u64, // tick within the loop, 0..steps
+    /// Ticks the note must be held for after it is hit; 0 for a tap.
+    #[serde(default)]
+    pub(crate) hold: u64,
     pub(crate) note: Note,
 }
 
@@ -420,140 +550,6 @@ fn advance_iteration(
     mut track: ResMut<Track>,
 ) {
     for _ev in ev_advance_iter.read() {
-        track.iteration += 1;
+        track.sequencer.skip_loop();
     }
 }
-
-const SAMPLE_SEQ_TWO: [Seq; 8] = [
-    Seq {
-        time: 1,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 2,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotO,
-        },
-    },
-    Seq {
-        time: 3,
-        note: Note {
-            s1: OscType::Triangle,
-            s2: None,
-            pot: PotType::PotI,
-        },
-    },
-    Seq {
-        time: 4,
-        note: Note {
-            s1: OscType::Sawtooth,
-            s2: None,
-            pot: PotType::PotL,
-        },
-    },
-    Seq {
-        time: 5,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 6,
-        note: Note {
-            s1: OscType::Square,
-            s2: None,
-            pot: PotType::PotK,
-        },
-    },
-    Seq {
-        time: 7,
-        note: Note {
-            s1: OscType::Square,
-            s2: None,
-            pot: PotType::PotO,
-        },
-    },
-    Seq {
-        time: 8,
-        note: Note {
-            s1: OscType::Sawtooth,
-            s2: None,
-            pot: PotType::PotK,
-        },
-    },
-];
-
-const _SAMPLE_SEQ: [Seq; 8] = [
-    Seq {
-        time: 5,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 35,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 65,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 105,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 155,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 205,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 255,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-    Seq {
-        time: 305,
-        note: Note {
-            s1: OscType::Sine,
-            s2: None,
-            pot: PotType::PotJ,
-        },
-    },
-];